@@ -0,0 +1,301 @@
+use pong::bot::{Bot, Bots, DEFAULT_TIMEOUT};
+use pong::cli::fail;
+use pong::config::Config;
+use pong::game::builder::{Controller, Player};
+use pong::game::components::{Difficulty, Side};
+use pong::game::input::InputConfig;
+use pong::game::raster::{load_font, RasterRenderer};
+use pong::game::replay::{Replay, ReplaySettings};
+use pong::game::resources::MatchRules;
+use pong::game::timestep::DEFAULT_TICK_RATE;
+use pong::headless::{run_match_observed, summarize, MatchOptions, MatchResult, Summary};
+use serde_derive::Serialize;
+use std::str::FromStr;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: pong-headless [options]
+
+Plays matches without a window and prints their results as JSON.
+
+Players:
+  --top <controller>        Controller of the top paddle, normal by default
+  --bottom <controller>     Controller of the bottom paddle, normal by default
+                            A controller is an AI level (easy, normal or hard), script to
+                            use the inputs of the script, bot:<command> to start a bot or
+                            socket:<address> to connect to one
+  --bot-timeout <ms>        Milliseconds bots have to answer each tick, 10 by default
+
+Matches:
+  --matches <count>         Matches played, 10 by default
+  --seed <seed>             Seed of the first match, the next ones using the following seeds
+  --points <points>         Points needed to win a set, 5 or those of the config by default
+  --win-by-two              Only ends a set with a lead of at least two points
+  --best-of <sets>          Sets of a match, 1 by default
+  --time-limit <seconds>    Seconds of play after which the leading player wins the set
+  --max-seconds <seconds>   Seconds after which a match is stopped, 300 by default
+  --script <file>           Replay whose inputs move the script paddles
+  --config <file>           Settings of the ball, the paddles, the arena and the rules, which
+                            the options above override
+
+Snapshots:
+  --snapshots <tick,...>    Ticks at which PNG images of each match are saved
+  --snapshot-dir <dir>      Folder of the snapshots, the current one by default
+
+  --help                    Prints this message";
+
+#[derive(Serialize)]
+struct Report {
+    seed: u64,
+    settings: ReplaySettings,
+    summary: Summary,
+    matches: Vec<MatchResult>,
+}
+
+fn main() {
+    let args = Args::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        std::process::exit(2);
+    });
+    if args.help {
+        println!("{}", USAGE);
+        return;
+    }
+    // the default config file is meant for the window, only an explicit one is read
+    let config = match args.config {
+        Some(ref path) => Config::load_or_default(Some(path)).unwrap_or_else(|e| fail(&e)),
+        None => Config {
+            rules: MatchRules {
+                points: 5,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    };
+    let script = args.script.as_ref().map(|path| {
+        Replay::load(path)
+            .unwrap_or_else(|e| fail(&format!("Failed to load script '{}': {}", path, e)))
+    });
+    let settings = ReplaySettings {
+        tick_rate: DEFAULT_TICK_RATE,
+        players: vec![
+            player(Side::Top, &args.top),
+            player(Side::Bottom, &args.bottom),
+        ],
+        lives: None,
+        rules: MatchRules {
+            points: args.points.unwrap_or(config.rules.points),
+            win_by_two: args.win_by_two || config.rules.win_by_two,
+            best_of: args.best_of.unwrap_or(config.rules.best_of),
+            time_limit: args.time_limit.or(config.rules.time_limit),
+            ..config.rules
+        },
+        ball: config.ball,
+        paddle: config.paddle,
+        bounce: config.bounce,
+        // scripted inputs only make sense with the bindings they were recorded with
+        bindings: script.as_ref().map_or_else(InputConfig::default, |script| {
+            script.settings.bindings.clone()
+        }),
+    };
+    let mut bots = Bots::new(args.bot_timeout);
+    for (side, seat) in &[(Side::Top, &args.top), (Side::Bottom, &args.bottom)] {
+        let bot = match seat {
+            Seat::Bot(command) => Bot::spawn(command)
+                .unwrap_or_else(|e| fail(&format!("Failed to start bot '{}': {}", command, e))),
+            Seat::Socket(address) => Bot::connect(address).unwrap_or_else(|e| {
+                fail(&format!("Failed to connect to bot '{}': {}", address, e))
+            }),
+            _ => continue,
+        };
+        bots.add(*side, bot);
+    }
+    let options = MatchOptions {
+        max_ticks: (args.max_seconds * f64::from(DEFAULT_TICK_RATE)) as u64,
+        arena: config.arena,
+    };
+
+    let mut raster = if args.snapshots.is_empty() {
+        None
+    } else {
+        let font = load_font("assets/arial.ttf")
+            .unwrap_or_else(|e| fail(&format!("Failed to load the font: {}", e)));
+        Some(RasterRenderer::new(
+            config.window.width,
+            config.window.height,
+            font,
+        ))
+    };
+    let matches: Vec<MatchResult> = (0..args.matches)
+        .map(|i| {
+            let seed = args.seed.wrapping_add(i);
+            run_match_observed(seed, &settings, &options, script.as_ref(), |game| {
+                bots.play(game);
+                let raster = match raster {
+                    Some(ref mut raster) if args.snapshots.contains(&game.tick()) => raster,
+                    _ => return,
+                };
+                raster.rasterize(&game.scene());
+                let path = format!(
+                    "{}/snapshot-{}-{}.png",
+                    args.snapshot_dir,
+                    seed,
+                    game.tick()
+                );
+                if let Err(e) = raster.frame.save(&path) {
+                    eprintln!("Failed to save snapshot to '{}': {}", path, e);
+                }
+            })
+        })
+        .collect();
+
+    let report = Report {
+        seed: args.seed,
+        summary: summarize(&matches, settings.tick_rate),
+        settings,
+        matches,
+    };
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+}
+
+struct Args {
+    matches: u64,
+    seed: u64,
+    top: Seat,
+    bottom: Seat,
+    points: Option<u32>,
+    win_by_two: bool,
+    best_of: Option<u32>,
+    time_limit: Option<f64>,
+    max_seconds: f64,
+    script: Option<String>,
+    config: Option<String>,
+    snapshots: Vec<u64>,
+    snapshot_dir: String,
+    bot_timeout: Duration,
+    help: bool,
+}
+
+/// What controls a paddle.
+enum Seat {
+    Ai(Difficulty),
+    /// The inputs of the script.
+    Script,
+    /// A program started by the simulation, talking through its standard input and output.
+    Bot(String),
+    /// A program listening on a local socket.
+    Socket(String),
+}
+
+impl FromStr for Seat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(command) = s.strip_prefix("bot:") {
+            return Ok(Seat::Bot(command.to_string()));
+        }
+        if let Some(address) = s.strip_prefix("socket:") {
+            return Ok(Seat::Socket(address.to_string()));
+        }
+        match s {
+            "script" => Ok(Seat::Script),
+            _ => s
+                .parse()
+                .map(Seat::Ai)
+                .map_err(|_| format!("Unknown controller '{}'", s)),
+        }
+    }
+}
+
+impl Args {
+    /// Reads the arguments following the name of the binary.
+    fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+        let mut parsed = Args {
+            matches: 10,
+            seed: rand::random(),
+            top: Seat::Ai(Difficulty::Normal),
+            bottom: Seat::Ai(Difficulty::Normal),
+            points: None,
+            win_by_two: false,
+            best_of: None,
+            time_limit: None,
+            max_seconds: 300.0,
+            script: None,
+            config: None,
+            snapshots: Vec::new(),
+            snapshot_dir: ".".to_string(),
+            bot_timeout: DEFAULT_TIMEOUT,
+            help: false,
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--help" | "-h" => parsed.help = true,
+                "--win-by-two" => parsed.win_by_two = true,
+                _ => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("Missing value after '{}'", arg))?;
+                    parsed.parse_option(&arg, value)?;
+                }
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn parse_option(&mut self, arg: &str, value: String) -> Result<(), String> {
+        let number = |name: &str| format!("{} must be a positive integer", name);
+        match arg {
+            "--matches" => self.matches = value.parse().map_err(|_| number("Matches"))?,
+            "--seed" => self.seed = value.parse().map_err(|_| number("Seed"))?,
+            "--top" => self.top = value.parse()?,
+            "--bottom" => self.bottom = value.parse()?,
+            "--points" => self.points = Some(positive(&value).ok_or_else(|| number("Points"))?),
+            "--best-of" => self.best_of = Some(positive(&value).ok_or_else(|| number("Sets"))?),
+            "--time-limit" => {
+                let seconds = value
+                    .parse()
+                    .ok()
+                    .filter(|seconds: &f64| *seconds > 0.0)
+                    .ok_or_else(|| "Time limit must be a positive number".to_string())?;
+                self.time_limit = Some(seconds);
+            }
+            "--max-seconds" => {
+                self.max_seconds = value
+                    .parse()
+                    .map_err(|_| "Max seconds must be a number".to_string())?
+            }
+            "--script" => self.script = Some(value),
+            "--config" => self.config = Some(value),
+            "--snapshots" => {
+                self.snapshots = value
+                    .split(',')
+                    .map(|tick| tick.parse().map_err(|_| number("Snapshot ticks")))
+                    .collect::<Result<_, _>>()?
+            }
+            "--snapshot-dir" => self.snapshot_dir = value,
+            "--bot-timeout" => {
+                let milliseconds = value.parse().map_err(|_| number("Bot timeout"))?;
+                self.bot_timeout = Duration::from_millis(milliseconds);
+            }
+            _ => return Err(format!("Unknown argument '{}'", arg)),
+        }
+        Ok(())
+    }
+}
+
+/// Reads a number above 0.
+fn positive(value: &str) -> Option<u32> {
+    value.parse().ok().filter(|number| *number > 0)
+}
+
+fn player(side: Side, seat: &Seat) -> Player {
+    Player {
+        side,
+        controller: match seat {
+            Seat::Ai(difficulty) => Controller::Ai(*difficulty),
+            Seat::Script => Controller::Keyboard,
+            Seat::Bot(_) | Seat::Socket(_) => Controller::Agent,
+        },
+    }
+}
@@ -0,0 +1,209 @@
+//! Plays pong in a terminal, for instance over SSH, taking the options of `pong` apart from
+//! those of the window and of replays.
+//!
+//! Terminals only report key presses, repeated while a key is held, so a key is considered
+//! released once it stops repeating for a while. Moving a paddle therefore lags behind the
+//! keyboard a bit more than in the window.
+
+#[cfg(unix)]
+fn main() {
+    tui::run();
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("The terminal frontend needs a Unix terminal");
+    std::process::exit(1);
+}
+
+#[cfg(unix)]
+mod tui {
+    use piston::input::{Button, Key};
+    use pong::cli::{fail, Args, USAGE};
+    use pong::config::Config;
+    use pong::game::builder::GameBuilder;
+    use pong::game::input::{Binding, InputConfig, InputKind};
+    use pong::game::render::Viewport;
+    use pong::game::terminal::TerminalRenderer;
+    use pong::game::timestep::FixedTimestep;
+    use pong::game::Game;
+    use std::collections::HashMap;
+    use std::io::{self, Write};
+    use std::time::{Duration, Instant};
+    use termion::event::Key as TermKey;
+    use termion::input::TermRead;
+    use termion::raw::IntoRawMode;
+    use termion::screen::AlternateScreen;
+
+    /// Pixels of the window covered by a character, about twice as high as wide like the
+    /// characters of most terminals.
+    const CELL_WIDTH: u32 = 5;
+    const CELL_HEIGHT: u32 = 10;
+
+    /// Seconds a key stays pressed after it was first seen, long enough for the terminal to
+    /// start repeating it.
+    const FIRST_HOLD_SECONDS: f64 = 0.55;
+
+    /// Seconds a key stays pressed after each repetition.
+    const REPEAT_HOLD_SECONDS: f64 = 0.1;
+
+    /// Time between two frames.
+    const FRAME: Duration = Duration::from_millis(16);
+
+    pub fn run() {
+        let args = Args::parse(std::env::args().skip(1))
+            .and_then(supported)
+            .unwrap_or_else(|e| {
+                eprintln!("{}\n\n{}", e, USAGE);
+                std::process::exit(2);
+            });
+        if args.help {
+            println!("{}", USAGE);
+            return;
+        }
+        let config = Config::load_or_default(args.config.as_deref()).unwrap_or_else(|e| fail(&e));
+        let bindings = InputConfig::load_or_default(&args.bindings).unwrap_or_else(|e| fail(&e));
+        let settings = args.settings(&config, bindings);
+
+        // the scene fills the terminal, apart from the margin around it
+        let (columns, rows) = termion::terminal_size()
+            .map(|(columns, rows)| (u32::from(columns), u32::from(rows)))
+            .unwrap_or((
+                config.window.width / CELL_WIDTH + 2,
+                config.window.height / CELL_HEIGHT + 2,
+            ));
+        let (columns, rows) = (
+            columns.saturating_sub(2).max(1),
+            rows.saturating_sub(2).max(1),
+        );
+        let (width, height) = (columns * CELL_WIDTH, rows * CELL_HEIGHT);
+        let arena = config.arena_in(width, height);
+
+        let screen = io::stdout()
+            .into_raw_mode()
+            .map(AlternateScreen::from)
+            .expect("Failed to switch the terminal to raw mode");
+        let mut renderer =
+            TerminalRenderer::new(width, height, columns as usize, rows as usize, screen);
+        write!(
+            renderer.output,
+            "{}{}",
+            termion::cursor::Hide,
+            termion::clear::All
+        )
+        .unwrap_or(());
+        let mut game = GameBuilder::new()
+            .seed(args.seed.unwrap_or_else(rand::random))
+            .replay_settings(&settings)
+            .menu()
+            .arena(arena.width, arena.height)
+            .render(renderer)
+            .build();
+
+        play(&mut game, settings.tick_rate, width, height);
+
+        // leaves the alternate screen and raw mode before showing the cursor again
+        drop(game);
+        print!("{}", termion::cursor::Show);
+        io::stdout().flush().unwrap_or(());
+    }
+
+    /// Runs the game until Ctrl-C, or the back action outside of a match in progress.
+    fn play(game: &mut Game, tick_rate: u32, width: u32, height: u32) {
+        let mut keys = termion::async_stdin().keys();
+        let mut held: HashMap<Button, Instant> = HashMap::new();
+        let mut timestep = FixedTimestep::new(tick_rate);
+        let mut last_frame = Instant::now();
+
+        loop {
+            let now = Instant::now();
+            while let Some(Ok(key)) = keys.next() {
+                if key == TermKey::Ctrl('c') {
+                    return;
+                }
+                let button = match to_button(key) {
+                    Some(button) => button,
+                    None => continue,
+                };
+                if game.quits_on(button) {
+                    return;
+                }
+                // the actions of the game happen on release, which is sent right away
+                let is_action = game
+                    .world
+                    .read_resource::<InputConfig>()
+                    .game
+                    .action(Binding::Button(button))
+                    .is_some();
+                if is_action {
+                    game.apply_input(InputKind::Press(button));
+                    game.apply_input(InputKind::Release(button));
+                    continue;
+                }
+                let hold = match held.get(&button) {
+                    Some(_) => REPEAT_HOLD_SECONDS,
+                    None => {
+                        game.apply_input(InputKind::Press(button));
+                        FIRST_HOLD_SECONDS
+                    }
+                };
+                held.insert(button, now + Duration::from_secs_f64(hold));
+            }
+
+            let released: Vec<Button> = held
+                .iter()
+                .filter(|(_, until)| **until <= now)
+                .map(|(button, _)| *button)
+                .collect();
+            for button in released {
+                held.remove(&button);
+                game.apply_input(InputKind::Release(button));
+            }
+
+            let elapsed = now.duration_since(last_frame).as_secs_f64();
+            last_frame = now;
+            for _ in 0..timestep.advance(elapsed) {
+                game.step();
+            }
+            game.render(Viewport::new(width, height));
+            std::thread::sleep(FRAME);
+        }
+    }
+
+    /// The piston button matching a key of the terminal, letters being matched whatever
+    /// their case.
+    fn to_button(key: TermKey) -> Option<Button> {
+        let key = match key {
+            TermKey::Left => Key::Left,
+            TermKey::Right => Key::Right,
+            TermKey::Up => Key::Up,
+            TermKey::Down => Key::Down,
+            TermKey::Esc => Key::Escape,
+            TermKey::Backspace => Key::Backspace,
+            TermKey::Char('\n') => Key::Return,
+            TermKey::Char('\t') => Key::Tab,
+            TermKey::Char(c) if c.is_ascii() => Key::from(c.to_ascii_lowercase() as u32),
+            _ => return None,
+        };
+        match key {
+            Key::Unknown => None,
+            key => Some(Button::Keyboard(key)),
+        }
+    }
+
+    /// Rejects the options of the window that a terminal cannot honour.
+    fn supported(args: Args) -> Result<Args, String> {
+        let window_only = [
+            ("--mouse", !args.mice.is_empty()),
+            ("--fullscreen", args.fullscreen),
+            ("--window", args.window.is_some()),
+            ("--record", args.record.is_some()),
+            ("--playback", args.playback.is_some()),
+            ("--headless", args.headless),
+        ];
+        match window_only.iter().find(|(_, used)| *used) {
+            Some((option, _)) => Err(format!("{} is not supported in a terminal", option)),
+            None => Ok(args),
+        }
+    }
+}
@@ -0,0 +1,383 @@
+//! Protocol letting external programs, written in any language, move paddles.
+//!
+//! Each tick the game sends every bot a line of JSON describing the game, a `TickMessage`,
+//! and waits for a line of JSON back, a `MoveMessage` with the same tick. Bots that do not
+//! answer in time, or answer something else, leave their paddle still for the tick, and so
+//! do bots that fall behind reading the messages.
+//!
+//! ```text
+//! > {"tick":42,"state":"Running","side":"Bottom","ball":{"x":0.5,"y":0.4,"vx":0.1,"vy":-0.5},"paddles":[...]}
+//! < {"tick":42,"move":"Left"}
+//! ```
+
+use crate::game::components::{Ball, Move, Paddle, Position, Score, Side, Velocity};
+use crate::game::{Game, State};
+use serde_derive::{Deserialize, Serialize};
+use specs::prelude::*;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time bots have to answer unless configured otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// Messages waiting to be written to a bot before the next ones are dropped.
+const MAX_PENDING_MESSAGES: usize = 8;
+
+/// Position and velocity of an entity, in normalized coordinates.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Body {
+    pub x: f64,
+    pub y: f64,
+    pub vx: f64,
+    pub vy: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PaddleMessage {
+    pub side: Side,
+    #[serde(flatten)]
+    pub body: Body,
+    /// Points in the current set.
+    pub score: u32,
+}
+
+/// What a bot is told before each tick.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TickMessage {
+    pub tick: u64,
+    pub state: State,
+    /// Side of the paddle the bot moves.
+    pub side: Side,
+    pub ball: Option<Body>,
+    pub paddles: Vec<PaddleMessage>,
+}
+
+impl TickMessage {
+    /// Describes the game to the bot on `side`.
+    pub fn new(game: &Game, side: Side) -> Self {
+        let world = &game.world;
+        let positions = world.read_storage::<Position>();
+        let velocities = world.read_storage::<Velocity>();
+        let balls = world.read_storage::<Ball>();
+        let paddles = world.read_storage::<Paddle>();
+        let scores = world.read_storage::<Score>();
+        let body = |position: &Position, velocity: &Velocity| {
+            let velocity = velocity.direction * velocity.speed;
+            Body {
+                x: position.current.x,
+                y: position.current.y,
+                vx: velocity.x,
+                vy: velocity.y,
+            }
+        };
+
+        TickMessage {
+            tick: game.tick(),
+            state: game.state(),
+            side,
+            ball: (&positions, &velocities, &balls)
+                .join()
+                .map(|(position, velocity, _)| body(position, velocity))
+                .next(),
+            paddles: (&positions, &velocities, &paddles, scores.maybe())
+                .join()
+                .map(|(position, velocity, paddle, score)| PaddleMessage {
+                    side: paddle.side,
+                    body: body(position, velocity),
+                    score: score.map_or(0, |score| score.current),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// What a bot answers for a tick.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MoveMessage {
+    pub tick: u64,
+    #[serde(rename = "move")]
+    pub command: Move,
+}
+
+/// A connection to a bot.
+pub struct Bot {
+    messages: SyncSender<String>,
+    lines: Receiver<String>,
+    child: Option<Child>,
+}
+
+impl Bot {
+    /// Talks to a bot through `writer` and `reader`, each on its own thread so that a bot
+    /// neither reading nor answering cannot block the game.
+    pub fn new<R, W>(reader: R, writer: W) -> Self
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            // stops once the bot is gone or nobody listens anymore
+            for line in BufReader::new(reader).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    return;
+                }
+            }
+        });
+        let (messages, pending) = mpsc::sync_channel::<String>(MAX_PENDING_MESSAGES);
+        thread::spawn(move || {
+            let mut writer = writer;
+            for message in pending {
+                if writeln!(writer, "{}", message)
+                    .and_then(|_| writer.flush())
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        });
+        Bot {
+            messages,
+            lines,
+            child: None,
+        }
+    }
+
+    /// Starts `command`, a program followed by its arguments separated by spaces, and talks to
+    /// it through its standard input and output.
+    pub fn spawn(command: &str) -> io::Result<Self> {
+        let mut parts = command.split_whitespace();
+        let program = parts
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Empty bot command"))?;
+        let mut child = Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let (stdin, stdout) = (child.stdin.take().unwrap(), child.stdout.take().unwrap());
+        let mut bot = Bot::new(stdout, stdin);
+        bot.child = Some(child);
+        Ok(bot)
+    }
+
+    /// Connects to a bot listening on `address`, such as `127.0.0.1:4000`.
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        Ok(Bot::new(stream.try_clone()?, stream))
+    }
+
+    /// Queues `message` for the bot, failing if the bot is gone or still has too many
+    /// messages to read.
+    pub fn send(&mut self, message: &TickMessage) -> io::Result<()> {
+        let line = serde_json::to_string(message)?;
+        self.messages.try_send(line).map_err(|e| match e {
+            TrySendError::Full(_) => {
+                io::Error::new(io::ErrorKind::WouldBlock, "The bot is not reading")
+            }
+            TrySendError::Disconnected(_) => {
+                io::Error::new(io::ErrorKind::BrokenPipe, "The bot is gone")
+            }
+        })
+    }
+
+    /// Waits until `deadline` for the move of `tick`, skipping late answers to previous ticks.
+    pub fn receive(&mut self, tick: u64, deadline: Instant) -> Option<Move> {
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let line = self.lines.recv_timeout(timeout).ok()?;
+            match serde_json::from_str::<MoveMessage>(&line) {
+                Ok(message) if message.tick == tick => return Some(message.command),
+                _ => continue,
+            }
+        }
+    }
+}
+
+impl Drop for Bot {
+    fn drop(&mut self) {
+        if let Some(ref mut child) = self.child {
+            child.kill().unwrap_or(());
+            child.wait().map(|_| ()).unwrap_or(());
+        }
+    }
+}
+
+/// Bots commanding the agents of a game, each on its side.
+pub struct Bots {
+    pub bots: Vec<(Side, Bot)>,
+    /// Time given to all the bots to answer, each tick.
+    pub timeout: Duration,
+}
+
+impl Bots {
+    pub fn new(timeout: Duration) -> Self {
+        Bots {
+            bots: Vec::new(),
+            timeout,
+        }
+    }
+
+    pub fn add(&mut self, side: Side, bot: Bot) {
+        self.bots.push((side, bot));
+    }
+
+    /// Describes the game to every bot, then commands their paddles with their answers for
+    /// the next tick. Bots think in parallel, sharing the same deadline.
+    pub fn play(&mut self, game: &mut Game) {
+        let tick = game.tick();
+        let mut reachable = Vec::with_capacity(self.bots.len());
+        for (side, bot) in &mut self.bots {
+            reachable.push(bot.send(&TickMessage::new(game, *side)).is_ok());
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        for ((side, bot), reachable) in self.bots.iter_mut().zip(reachable) {
+            let command = if reachable {
+                bot.receive(tick, deadline)
+            } else {
+                None
+            };
+            game.command(*side, command.unwrap_or(Move::Stay));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::components::Agent;
+    use crate::game::{Controller, GameBuilder};
+    use std::net::TcpListener;
+
+    fn command(game: &Game, side: Side) -> Move {
+        let paddles = game.world.read_storage::<Paddle>();
+        let agents = game.world.read_storage::<Agent>();
+        (&paddles, &agents)
+            .join()
+            .find(|(paddle, _)| paddle.side == side)
+            .map(|(_, agent)| agent.command)
+            .unwrap()
+    }
+
+    /// Accepts a bot connection and answers the message of each tick and side with `answer`,
+    /// if any.
+    fn listen<F>(answer: F) -> String
+    where
+        F: Fn(u64, Side) -> Option<String> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines() {
+                let value: serde_json::Value = serde_json::from_str(&line.unwrap()).unwrap();
+                assert_eq!(2, value["paddles"].as_array().unwrap().len());
+                let tick = value["tick"].as_u64().unwrap();
+                let side = serde_json::from_value(value["side"].clone()).unwrap();
+                if let Some(answer) = answer(tick, side) {
+                    writeln!(writer, "{}", answer).unwrap();
+                }
+            }
+        });
+        address
+    }
+
+    #[test]
+    fn it_should_move_paddles_as_the_bots_answer() {
+        let mut game = GameBuilder::new()
+            .seed(1)
+            .top_player(Controller::Agent)
+            .bottom_player(Controller::Agent)
+            .build();
+        let mut bots = Bots::new(Duration::from_secs(5));
+        for side in &[Side::Top, Side::Bottom] {
+            let address = listen(|tick, side| {
+                let command = match side {
+                    Side::Top => Move::Left,
+                    _ => Move::Right,
+                };
+                // a late answer to a previous tick comes first
+                Some(format!(
+                    "{{\"tick\":{},\"move\":\"Stay\"}}\n{}",
+                    tick.wrapping_sub(1),
+                    serde_json::to_string(&MoveMessage { tick, command }).unwrap()
+                ))
+            });
+            bots.add(*side, Bot::connect(&address).unwrap());
+        }
+
+        bots.play(&mut game);
+        assert_eq!(Move::Left, command(&game, Side::Top));
+        assert_eq!(Move::Right, command(&game, Side::Bottom));
+    }
+
+    #[test]
+    fn it_should_stay_still_when_a_bot_does_not_answer_in_time() {
+        let mut game = GameBuilder::new()
+            .seed(1)
+            .bottom_player(Controller::Agent)
+            .build();
+        game.command(Side::Bottom, Move::Left);
+        let mut bots = Bots::new(Duration::from_millis(20));
+        bots.add(Side::Bottom, Bot::connect(&listen(|_, _| None)).unwrap());
+
+        let start = Instant::now();
+        bots.play(&mut game);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(Move::Stay, command(&game, Side::Bottom));
+    }
+
+    /// A bot that never reads what it is sent.
+    struct Stuck;
+
+    impl Write for Stuck {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            loop {
+                thread::park();
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_should_not_wait_for_a_bot_that_stops_reading() {
+        let mut game = GameBuilder::new()
+            .seed(1)
+            .top_player(Controller::Agent)
+            .bottom_player(Controller::Agent)
+            .build();
+        let mut bots = Bots::new(Duration::from_secs(1));
+        bots.add(Side::Top, Bot::new(io::empty(), Stuck));
+        bots.add(
+            Side::Bottom,
+            Bot::connect(&listen(|tick, _| {
+                Some(
+                    serde_json::to_string(&MoveMessage {
+                        tick,
+                        command: Move::Left,
+                    })
+                    .unwrap(),
+                )
+            }))
+            .unwrap(),
+        );
+
+        let start = Instant::now();
+        for _ in 0..2 * MAX_PENDING_MESSAGES {
+            bots.play(&mut game);
+            assert_eq!(Move::Stay, command(&game, Side::Top));
+            assert_eq!(Move::Left, command(&game, Side::Bottom));
+            game.step();
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
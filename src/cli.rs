@@ -0,0 +1,368 @@
+//! Command line shared by the `pong` and `pong-tui` binaries.
+
+use crate::config::Config;
+use crate::game::components::{Difficulty, Side};
+use crate::game::input::InputConfig;
+use crate::game::replay::ReplaySettings;
+use crate::game::resources::ServeRule;
+use crate::game::timestep::DEFAULT_TICK_RATE;
+use crate::game::{Controller, Player};
+use std::str::FromStr;
+
+/// File the bindings are read from and saved to when `--bindings` is not given.
+pub const DEFAULT_BINDINGS: &str = "bindings.toml";
+
+/// Lives of each player in a four players game.
+const LIVES: u32 = 3;
+
+pub const USAGE: &str = "\
+Usage: pong [options]
+
+Players:
+  --mode <mode>             1p (against the AI), 2p (default), ai (AI against AI) or 4p
+  --difficulty <level>      Level of the AIs picked by the mode: easy, normal (default) or hard
+  --ai-<side> <level>       Puts an AI on top, bottom, left or right, whatever the mode
+  --mouse <side>            Lets the player on top, bottom, left or right use the mouse
+  --four-players            Same as --mode 4p
+
+Match:
+  --seed <seed>             Seed of the random decisions, random by default
+  --points <points>         Points needed to win a set
+  --win-by-two              Only ends a set with a lead of at least two points
+  --best-of <sets>          Most sets played, the first player winning more than half wins
+  --time-limit <seconds>    Seconds of play after which the leading player wins the set
+  --serve <rule>            Who serves after a point: alternate, loser or winner
+  --manual-serve            Lets keyboard and mouse players release the ball when serving
+  --config <file>           Settings of the game, pong.toml if it exists by default
+  --bindings <file>         Bindings of the players, bindings.toml by default
+
+Display:
+  --fullscreen              Opens the game in fullscreen
+  --window <width>x<height> Size of the window, overriding the config and scaling the arena
+
+Replays:
+  --record <file>           Saves the inputs of the game to a replay
+  --playback <file>         Plays a replay back
+  --headless                Plays the match, or the replay, without a window and prints its result
+
+  --help                    Prints this message";
+
+/// Who plays the match.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
+    /// A keyboard player at the bottom against an AI at the top.
+    Solo,
+    /// Two keyboard players.
+    Versus,
+    /// Two AIs.
+    AiVersusAi,
+    /// Four keyboard players, each with lives.
+    FourPlayers,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1p" => Ok(Mode::Solo),
+            "2p" => Ok(Mode::Versus),
+            "ai" => Ok(Mode::AiVersusAi),
+            "4p" => Ok(Mode::FourPlayers),
+            _ => Err(format!("Unknown mode '{}'", s)),
+        }
+    }
+}
+
+pub struct Args {
+    pub mode: Mode,
+    pub difficulty: Difficulty,
+    pub ais: Vec<(Side, Difficulty)>,
+    pub mice: Vec<Side>,
+    pub seed: Option<u64>,
+    pub points: Option<u32>,
+    pub win_by_two: bool,
+    pub best_of: Option<u32>,
+    pub time_limit: Option<f64>,
+    pub serve: Option<ServeRule>,
+    pub manual_serve: bool,
+    pub config: Option<String>,
+    pub bindings: String,
+    pub fullscreen: bool,
+    pub window: Option<(u32, u32)>,
+    pub record: Option<String>,
+    pub playback: Option<String>,
+    pub headless: bool,
+    pub help: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            mode: Mode::Versus,
+            difficulty: Difficulty::Normal,
+            ais: Vec::new(),
+            mice: Vec::new(),
+            seed: None,
+            points: None,
+            win_by_two: false,
+            best_of: None,
+            time_limit: None,
+            serve: None,
+            manual_serve: false,
+            config: None,
+            bindings: DEFAULT_BINDINGS.to_string(),
+            fullscreen: false,
+            window: None,
+            record: None,
+            playback: None,
+            headless: false,
+            help: false,
+        }
+    }
+}
+
+impl Args {
+    /// Reads the arguments following the name of the binary.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--help" | "-h" => parsed.help = true,
+                "--four-players" => parsed.mode = Mode::FourPlayers,
+                "--fullscreen" => parsed.fullscreen = true,
+                "--headless" => parsed.headless = true,
+                "--win-by-two" => parsed.win_by_two = true,
+                "--manual-serve" => parsed.manual_serve = true,
+                _ => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("Missing value after '{}'", arg))?;
+                    parsed.parse_option(&arg, value)?;
+                }
+            }
+        }
+
+        if parsed.headless && parsed.record.is_some() {
+            return Err("--record cannot be used with --headless".to_string());
+        }
+        Ok(parsed)
+    }
+
+    fn parse_option(&mut self, arg: &str, value: String) -> Result<(), String> {
+        let number = |name: &str| format!("{} must be a positive integer", name);
+        match arg {
+            "--mode" => self.mode = value.parse()?,
+            "--difficulty" => self.difficulty = value.parse()?,
+            "--ai-top" => self.ais.push((Side::Top, value.parse()?)),
+            "--ai-bottom" => self.ais.push((Side::Bottom, value.parse()?)),
+            "--ai-left" => self.ais.push((Side::Left, value.parse()?)),
+            "--ai-right" => self.ais.push((Side::Right, value.parse()?)),
+            "--mouse" => self.mice.push(value.parse()?),
+            "--seed" => self.seed = Some(value.parse().map_err(|_| number("Seed"))?),
+            "--points" => {
+                let points = value.parse().map_err(|_| number("Points"))?;
+                if points == 0 {
+                    return Err(number("Points"));
+                }
+                self.points = Some(points);
+            }
+            "--best-of" => {
+                let sets = value.parse().map_err(|_| number("Sets"))?;
+                if sets == 0 {
+                    return Err(number("Sets"));
+                }
+                self.best_of = Some(sets);
+            }
+            "--time-limit" => {
+                let seconds = value
+                    .parse()
+                    .ok()
+                    .filter(|seconds: &f64| *seconds > 0.0)
+                    .ok_or_else(|| "Time limit must be a positive number".to_string())?;
+                self.time_limit = Some(seconds);
+            }
+            "--serve" => self.serve = Some(value.parse()?),
+            "--config" => self.config = Some(value),
+            "--bindings" => self.bindings = value,
+            "--window" => self.window = Some(parse_size(&value)?),
+            "--record" => self.record = Some(value),
+            "--playback" => self.playback = Some(value),
+            _ => return Err(format!("Unknown argument '{}'", arg)),
+        }
+        Ok(())
+    }
+
+    /// Settings of a match between the players of the chosen mode, following `config` unless
+    /// the arguments override it.
+    pub fn settings(&self, config: &Config, bindings: InputConfig) -> ReplaySettings {
+        let mut rules = config.rules;
+        rules.points = self.points.unwrap_or(rules.points);
+        rules.win_by_two |= self.win_by_two;
+        rules.best_of = self.best_of.unwrap_or(rules.best_of);
+        rules.time_limit = self.time_limit.or(rules.time_limit);
+        rules.serve = self.serve.unwrap_or(rules.serve);
+        rules.manual_serve |= self.manual_serve;
+        ReplaySettings {
+            tick_rate: DEFAULT_TICK_RATE,
+            players: self.players(),
+            lives: if self.mode == Mode::FourPlayers {
+                Some(LIVES)
+            } else {
+                None
+            },
+            rules,
+            ball: config.ball,
+            paddle: config.paddle,
+            bounce: config.bounce,
+            bindings,
+        }
+    }
+
+    /// Players of the chosen mode, with the AIs and mouse players asked for on their side.
+    pub fn players(&self) -> Vec<Player> {
+        let ai = Controller::Ai(self.difficulty);
+        let mode: &[(Side, Controller)] = match self.mode {
+            Mode::Solo => &[(Side::Top, ai), (Side::Bottom, Controller::Keyboard)],
+            Mode::Versus => &[
+                (Side::Top, Controller::Keyboard),
+                (Side::Bottom, Controller::Keyboard),
+            ],
+            Mode::AiVersusAi => &[(Side::Top, ai), (Side::Bottom, ai)],
+            Mode::FourPlayers => &[
+                (Side::Top, Controller::Keyboard),
+                (Side::Bottom, Controller::Keyboard),
+                (Side::Left, Controller::Keyboard),
+                (Side::Right, Controller::Keyboard),
+            ],
+        };
+        mode.iter()
+            .map(|(side, controller)| Player {
+                side: *side,
+                controller: match self.ais.iter().find(|(ai_side, _)| ai_side == side) {
+                    Some((_, difficulty)) => Controller::Ai(*difficulty),
+                    None if self.mice.contains(side) => Controller::Mouse,
+                    None => *controller,
+                },
+            })
+            .collect()
+    }
+}
+
+/// Prints `message` and stops with an error code, for errors found after the arguments were
+/// read.
+pub fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+/// Reads a size written as `<width>x<height>`.
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || {
+        format!(
+            "Invalid size '{}', expected <width>x<height> in pixels",
+            value
+        )
+    };
+    let mut parts = value.splitn(2, 'x');
+    let mut dimension = || {
+        parts
+            .next()
+            .and_then(|d| d.parse().ok())
+            .filter(|d| *d > 0)
+            .ok_or_else(invalid)
+    };
+    let width = dimension()?;
+    let height = dimension()?;
+    Ok((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn it_should_put_the_mode_players_on_their_sides() {
+        let args = parse(&["--mode", "1p", "--difficulty", "hard"]).unwrap();
+        assert_eq!(
+            vec![
+                Player {
+                    side: Side::Top,
+                    controller: Controller::Ai(Difficulty::Hard),
+                },
+                Player {
+                    side: Side::Bottom,
+                    controller: Controller::Keyboard,
+                },
+            ],
+            args.players()
+        );
+
+        let args = parse(&["--four-players", "--ai-left", "easy", "--mouse", "top"]).unwrap();
+        let controllers: Vec<Controller> = args.players().iter().map(|p| p.controller).collect();
+        assert_eq!(
+            vec![
+                Controller::Mouse,
+                Controller::Keyboard,
+                Controller::Ai(Difficulty::Easy),
+                Controller::Keyboard,
+            ],
+            controllers
+        );
+    }
+
+    #[test]
+    fn it_should_read_the_window_size() {
+        let args = parse(&["--window", "800x600", "--fullscreen"]).unwrap();
+        assert_eq!(Some((800, 600)), args.window);
+        assert!(args.fullscreen);
+        assert!(parse(&["--window", "800"]).is_err());
+        assert!(parse(&["--window", "0x600"]).is_err());
+    }
+
+    #[test]
+    fn it_should_override_the_rules_of_the_config() {
+        let mut config = Config::default();
+        config.rules.points = 7;
+        config.rules.serve = ServeRule::WinnerServes;
+        let args = parse(&[
+            "--serve",
+            "loser",
+            "--manual-serve",
+            "--best-of",
+            "3",
+            "--time-limit",
+            "90",
+        ])
+        .unwrap();
+        let rules = args.settings(&config, InputConfig::default()).rules;
+        assert_eq!(7, rules.points);
+        assert!(!rules.win_by_two);
+        assert_eq!(3, rules.best_of);
+        assert_eq!(Some(90.0), rules.time_limit);
+        assert_eq!(ServeRule::LoserServes, rules.serve);
+        assert!(rules.manual_serve);
+    }
+
+    #[test]
+    fn it_should_explain_what_is_wrong() {
+        assert_eq!(
+            Err("Unknown mode '3p'".to_string()),
+            parse(&["--mode", "3p"]).map(|_| ())
+        );
+        assert_eq!(
+            Err("Missing value after '--seed'".to_string()),
+            parse(&["--seed"]).map(|_| ())
+        );
+        assert!(parse(&["--points", "0"]).is_err());
+        assert!(parse(&["--serve", "random"]).is_err());
+        assert!(parse(&["--best-of", "0"]).is_err());
+        assert!(parse(&["--time-limit", "-5"]).is_err());
+        assert!(parse(&["--headless", "--record", "out.json"]).is_err());
+    }
+}
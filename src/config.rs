@@ -0,0 +1,278 @@
+//! Settings read from a TOML file so that the game can be tuned without recompiling.
+
+use crate::game::resources::{BallSettings, BounceSettings, MatchRules, PaddleSettings};
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// File the settings are read from when no other file is given.
+pub const DEFAULT_CONFIG: &str = "pong.toml";
+
+/// Size of the window, in pixels.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            width: 250,
+            height: 300,
+        }
+    }
+}
+
+/// Size of the arena drawn in the window, in pixels. The rest of the window shows the scores.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArenaConfig {
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Default for ArenaConfig {
+    fn default() -> Self {
+        ArenaConfig {
+            width: 200.0,
+            height: 300.0,
+        }
+    }
+}
+
+/// Every setting of the config file. Missing ones keep their default value.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub window: WindowConfig,
+    pub arena: ArenaConfig,
+    pub ball: BallSettings,
+    pub paddle: PaddleSettings,
+    pub bounce: BounceSettings,
+    pub rules: MatchRules,
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Config::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Reads the settings from `path`, or from `DEFAULT_CONFIG` when no path is given. Only a
+    /// missing `DEFAULT_CONFIG` falls back to the default settings.
+    pub fn load_or_default(path: Option<&str>) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => path,
+            None if !Path::new(DEFAULT_CONFIG).exists() => return Ok(Config::default()),
+            None => DEFAULT_CONFIG,
+        };
+        Config::load(path).map_err(|e| format!("Failed to load config '{}': {}", path, e))
+    }
+
+    /// Reads a config from TOML and checks that its values make sense.
+    pub fn parse(source: &str) -> io::Result<Self> {
+        let config: Config = toml::from_str(source)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        config
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(config)
+    }
+
+    /// The arena scaled to a window of `width` by `height` pixels, keeping its proportions and
+    /// the room it leaves for the scores in the window of the config.
+    pub fn arena_in(&self, width: u32, height: u32) -> ArenaConfig {
+        let scale = (f64::from(width) / f64::from(self.window.width))
+            .min(f64::from(height) / f64::from(self.window.height));
+        ArenaConfig {
+            width: self.arena.width * scale,
+            height: self.arena.height * scale,
+        }
+    }
+
+    /// Returns a message naming the first setting out of its range, if any.
+    pub fn validate(&self) -> Result<(), String> {
+        let check = |valid: bool, name: &str, range: &str, value: f64| {
+            if valid {
+                Ok(())
+            } else {
+                Err(format!("{} must be {}, got {}", name, range, value))
+            }
+        };
+        let (window, arena, ball, paddle, bounce, rules) = (
+            self.window,
+            self.arena,
+            self.ball,
+            self.paddle,
+            self.bounce,
+            self.rules,
+        );
+
+        check(
+            window.width > 0,
+            "window.width",
+            "positive",
+            f64::from(window.width),
+        )?;
+        check(
+            window.height > 0,
+            "window.height",
+            "positive",
+            f64::from(window.height),
+        )?;
+        check(
+            arena.width > 0.0 && arena.width <= f64::from(window.width),
+            "arena.width",
+            "positive and at most window.width",
+            arena.width,
+        )?;
+        check(
+            arena.height > 0.0 && arena.height <= f64::from(window.height),
+            "arena.height",
+            "positive and at most window.height",
+            arena.height,
+        )?;
+
+        check(
+            ball.radius > 0.0 && ball.radius < 0.5,
+            "ball.radius",
+            "between 0 and 0.5",
+            ball.radius,
+        )?;
+        check(ball.speed > 0.0, "ball.speed", "positive", ball.speed)?;
+        check(
+            ball.max_speed >= ball.speed,
+            "ball.max_speed",
+            "at least ball.speed",
+            ball.max_speed,
+        )?;
+        check(
+            ball.speed_up >= 1.0,
+            "ball.speed_up",
+            "at least 1",
+            ball.speed_up,
+        )?;
+
+        check(
+            paddle.length > 0.0 && paddle.length <= 1.0,
+            "paddle.length",
+            "between 0 and 1",
+            paddle.length,
+        )?;
+        check(
+            paddle.thickness > 0.0 && paddle.thickness < 0.5,
+            "paddle.thickness",
+            "between 0 and 0.5",
+            paddle.thickness,
+        )?;
+        check(
+            paddle.max_speed > 0.0,
+            "paddle.max_speed",
+            "positive",
+            paddle.max_speed,
+        )?;
+
+        check(
+            bounce.max_angle >= 0.0 && bounce.max_angle < std::f64::consts::FRAC_PI_2,
+            "bounce.max_angle",
+            "between 0 and pi / 2",
+            bounce.max_angle,
+        )?;
+        check(
+            bounce.paddle_influence >= 0.0 && bounce.paddle_influence <= 1.0,
+            "bounce.paddle_influence",
+            "between 0 and 1",
+            bounce.paddle_influence,
+        )?;
+
+        check(
+            rules.points > 0,
+            "rules.points",
+            "positive",
+            f64::from(rules.points),
+        )?;
+        check(
+            rules.best_of > 0,
+            "rules.best_of",
+            "positive",
+            f64::from(rules.best_of),
+        )?;
+        let time_limit = rules.time_limit.unwrap_or(1.0);
+        check(time_limit > 0.0, "rules.time_limit", "positive", time_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::resources::ServeRule;
+
+    #[test]
+    fn it_should_ship_the_default_config() {
+        assert_eq!(
+            Config::default(),
+            Config::parse(include_str!("../pong.toml")).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_should_keep_defaults_for_missing_settings() {
+        let config = Config::parse("[ball]\nspeed_up = 1.2\n").unwrap();
+        assert_eq!(1.2, config.ball.speed_up);
+        assert_eq!(BallSettings::default().radius, config.ball.radius);
+        assert_eq!(WindowConfig::default(), config.window);
+    }
+
+    #[test]
+    fn it_should_name_the_invalid_setting() {
+        let error = Config::parse("[paddle]\nlength = 1.5\n").unwrap_err();
+        assert_eq!(
+            "paddle.length must be between 0 and 1, got 1.5",
+            error.to_string()
+        );
+        let error = Config::parse("[ball]\nspeed = 3.0\n").unwrap_err();
+        assert!(error.to_string().starts_with("ball.max_speed"));
+    }
+
+    #[test]
+    fn it_should_not_fall_back_to_the_defaults_for_a_missing_config_given_explicitly() {
+        let error = Config::load_or_default(Some("missing.toml")).unwrap_err();
+        assert!(error.starts_with("Failed to load config 'missing.toml'"));
+    }
+
+    #[test]
+    fn it_should_scale_the_arena_to_the_window() {
+        let config = Config::default();
+        assert_eq!(
+            ArenaConfig {
+                width: 400.0,
+                height: 600.0,
+            },
+            config.arena_in(500, 600)
+        );
+        // a wider window keeps the proportions of the arena
+        assert_eq!(
+            ArenaConfig {
+                width: 200.0,
+                height: 300.0,
+            },
+            config.arena_in(1000, 300)
+        );
+    }
+
+    #[test]
+    fn it_should_read_the_match_rules() {
+        let config =
+            Config::parse("[rules]\nserve = \"LoserServes\"\nmanual_serve = true\n").unwrap();
+        assert_eq!(ServeRule::LoserServes, config.rules.serve);
+        assert!(config.rules.manual_serve);
+        assert_eq!(MatchRules::default().points, config.rules.points);
+    }
+
+    #[test]
+    fn it_should_reject_unknown_settings() {
+        assert!(Config::parse("[ball]\nraduis = 0.1\n").is_err());
+    }
+}
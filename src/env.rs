@@ -0,0 +1,332 @@
+//! Environment for training paddle agents, in the style of OpenAI Gym.
+//!
+//! The players whose controller is `Controller::Agent` are the agents of the environment.
+//! With an AI on the other side a single agent learns against it, with agents on both sides
+//! the environment plays against itself.
+
+use crate::game::components::{Ball, Move, Paddle, Position, Score, Side, Velocity};
+use crate::game::input::{Action, InputConfig};
+use crate::game::replay::ReplaySettings;
+use crate::game::resources::{
+    BallSettings, BounceSettings, MatchRules, MatchStatus, PaddleSettings,
+};
+use crate::game::timestep::DEFAULT_TICK_RATE;
+use crate::game::{Controller, Game, GameBuilder, Player, State};
+use serde_derive::Serialize;
+use specs::prelude::*;
+
+/// What the agents see of the game, in normalized coordinates: the ball's position and
+/// velocity, followed by the position and velocity of each paddle in the order of the
+/// players, 4 values per entity.
+pub type Observation = Vec<f64>;
+
+pub struct EnvOptions {
+    /// Ticks simulated by each step, during which the commands of the agents hold.
+    pub ticks_per_step: u32,
+    /// Ticks after which an episode is stopped even if nobody won.
+    pub max_ticks: u64,
+}
+
+impl Default for EnvOptions {
+    fn default() -> Self {
+        EnvOptions {
+            ticks_per_step: 4,
+            max_ticks: u64::from(DEFAULT_TICK_RATE) * 300,
+        }
+    }
+}
+
+/// Details of a step that agents do not learn from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Info {
+    pub tick: u64,
+    /// Points of each player, in the order of the players.
+    pub scores: Vec<u32>,
+    pub winner: Option<Side>,
+    /// Whether the episode was stopped by `max_ticks` rather than won.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub observation: Observation,
+    /// Reward of each agent, in the order of the agents.
+    pub rewards: Vec<f64>,
+    pub done: bool,
+    pub info: Info,
+}
+
+/// A match played one step at a time by its agents.
+///
+/// Serves are taken automatically, and the ticks spent waiting for the ball to be served are
+/// skipped since paddles cannot move then.
+pub struct Env {
+    settings: ReplaySettings,
+    options: EnvOptions,
+    agents: Vec<Side>,
+    game: Game<'static, 'static>,
+    /// Points in the current set and sets won by each player, in the order of the players.
+    scores: Vec<(u32, u32)>,
+}
+
+impl Env {
+    pub fn new(settings: ReplaySettings, options: EnvOptions) -> Self {
+        let agents: Vec<Side> = settings
+            .players
+            .iter()
+            .filter(|player| player.controller == Controller::Agent)
+            .map(|player| player.side)
+            .collect();
+        assert!(
+            !agents.is_empty(),
+            "An environment needs at least one agent"
+        );
+        let game = GameBuilder::new().replay_settings(&settings).build();
+        let scores = vec![(0, 0); settings.players.len()];
+        Env {
+            settings,
+            options,
+            agents,
+            game,
+            scores,
+        }
+    }
+
+    /// An agent at the bottom against `opponent` at the top.
+    pub fn single_agent(opponent: Controller, options: EnvOptions) -> Self {
+        Env::new(two_players(opponent), options)
+    }
+
+    /// Agents at the top and at the bottom playing against each other.
+    pub fn self_play(options: EnvOptions) -> Self {
+        Env::new(two_players(Controller::Agent), options)
+    }
+
+    /// Sides of the agents, in the order their actions and rewards are given.
+    pub fn agents(&self) -> &[Side] {
+        &self.agents
+    }
+
+    pub fn game(&self) -> &Game<'static, 'static> {
+        &self.game
+    }
+
+    /// Starts a new episode, the same `seed` always leading to the same episode for the same
+    /// actions.
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.game = GameBuilder::new()
+            .seed(seed)
+            .replay_settings(&self.settings)
+            .build();
+        self.serve();
+        self.scores = self.read_scores();
+        self.observe()
+    }
+
+    /// Moves each agent's paddle following `actions`, given in the order of the agents, and
+    /// simulates `ticks_per_step` ticks.
+    ///
+    /// An agent is rewarded 1 for each point it scores and -1 for each point scored by
+    /// another player. A set ended by its time limit counts as a point for its winner.
+    pub fn step(&mut self, actions: &[Move]) -> Step {
+        assert_eq!(
+            self.agents.len(),
+            actions.len(),
+            "Expected one action per agent"
+        );
+        for (side, action) in self.agents.iter().zip(actions) {
+            self.game.command(*side, *action);
+        }
+        let mut gains = vec![0.0; self.scores.len()];
+        for _ in 0..self.options.ticks_per_step {
+            if self.game.state() == State::GameOver {
+                break;
+            }
+            self.game.step();
+            self.serve();
+
+            // points are checked after each tick since winning a set resets them
+            let scores = self.read_scores();
+            for (gain, (old, new)) in gains.iter_mut().zip(self.scores.iter().zip(&scores)) {
+                *gain += f64::from(points_scored(*old, *new));
+            }
+            self.scores = scores;
+        }
+        let total: f64 = gains.iter().sum();
+        let rewards = self
+            .agents
+            .iter()
+            .map(|side| {
+                let own = self
+                    .settings
+                    .players
+                    .iter()
+                    .position(|player| player.side == *side)
+                    .map_or(0.0, |i| gains[i]);
+                own - (total - own)
+            })
+            .collect();
+
+        let over = self.game.state() == State::GameOver;
+        let truncated = !over && self.game.tick() >= self.options.max_ticks;
+        Step {
+            observation: self.observe(),
+            rewards,
+            done: over || truncated,
+            info: Info {
+                tick: self.game.tick(),
+                scores: self.scores.iter().map(|(points, _)| *points).collect(),
+                winner: self.game.world.read_resource::<MatchStatus>().winner,
+                truncated,
+            },
+        }
+    }
+
+    /// Serves the ball, or waits for the countdown, until it is in play.
+    fn serve(&mut self) {
+        while self.game.state() == State::Serving {
+            self.game.perform(Action::Serve);
+            if self.game.state() == State::Serving {
+                self.game.step();
+            }
+        }
+    }
+
+    fn observe(&self) -> Observation {
+        let world = &self.game.world;
+        let positions = world.read_storage::<Position>();
+        let velocities = world.read_storage::<Velocity>();
+        let balls = world.read_storage::<Ball>();
+        let paddles = world.read_storage::<Paddle>();
+        let entity = |position: &Position, velocity: &Velocity| {
+            let velocity = velocity.direction * velocity.speed;
+            vec![
+                position.current.x,
+                position.current.y,
+                velocity.x,
+                velocity.y,
+            ]
+        };
+
+        let mut observation: Observation = (&positions, &velocities, &balls)
+            .join()
+            .flat_map(|(position, velocity, _)| entity(position, velocity))
+            .collect();
+        for player in &self.settings.players {
+            if let Some((position, velocity, _)) = (&positions, &velocities, &paddles)
+                .join()
+                .find(|(_, _, paddle)| paddle.side == player.side)
+            {
+                observation.extend(entity(position, velocity));
+            }
+        }
+        observation
+    }
+
+    fn read_scores(&self) -> Vec<(u32, u32)> {
+        let world = &self.game.world;
+        let paddles = world.read_storage::<Paddle>();
+        let scores = world.read_storage::<Score>();
+        self.settings
+            .players
+            .iter()
+            .map(|player| {
+                (&paddles, &scores)
+                    .join()
+                    .find(|(paddle, _)| paddle.side == player.side)
+                    .map_or((0, 0), |(_, score)| (score.current, score.sets))
+            })
+            .collect()
+    }
+}
+
+/// Points scored between two ticks by a player whose points and sets went from `old` to
+/// `new`.
+fn points_scored(old: (u32, u32), new: (u32, u32)) -> u32 {
+    let ((old_points, old_sets), (points, sets)) = (old, new);
+    // the points are reset once a set is won, unless it was the last one
+    let scored = points.saturating_sub(old_points);
+    if sets > old_sets {
+        scored.max(1)
+    } else {
+        scored
+    }
+}
+
+/// A match with the default rules between `top` and an agent at the bottom.
+fn two_players(top: Controller) -> ReplaySettings {
+    ReplaySettings {
+        tick_rate: DEFAULT_TICK_RATE,
+        players: vec![
+            Player {
+                side: Side::Top,
+                controller: top,
+            },
+            Player {
+                side: Side::Bottom,
+                controller: Controller::Agent,
+            },
+        ],
+        lives: None,
+        rules: MatchRules::default(),
+        ball: BallSettings::default(),
+        paddle: PaddleSettings::default(),
+        bounce: BounceSettings::default(),
+        bindings: InputConfig::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::components::Difficulty;
+
+    fn play(env: &mut Env, seed: u64, action: Move) -> Vec<Step> {
+        env.reset(seed);
+        let actions = vec![action; env.agents().len()];
+        let mut steps = Vec::new();
+        loop {
+            let step = env.step(&actions);
+            let done = step.done;
+            steps.push(step);
+            if done {
+                return steps;
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_replay_an_episode_from_its_seed() {
+        let mut env = Env::single_agent(Controller::Ai(Difficulty::Hard), EnvOptions::default());
+        let first = play(&mut env, 3, Move::Right);
+        let second = play(&mut env, 3, Move::Right);
+        assert_eq!(first, second);
+        assert_eq!(12, first[0].observation.len());
+    }
+
+    #[test]
+    fn it_should_punish_an_agent_for_the_points_it_loses() {
+        let mut env = Env::single_agent(Controller::Ai(Difficulty::Hard), EnvOptions::default());
+        let steps = play(&mut env, 3, Move::Stay);
+        let last = steps.last().unwrap();
+        assert_eq!(Some(Side::Top), last.info.winner);
+        assert!(!last.info.truncated);
+        let total: f64 = steps.iter().map(|step| step.rewards[0]).sum();
+        assert_eq!(-f64::from(MatchRules::default().points), total);
+    }
+
+    #[test]
+    fn it_should_reward_agents_in_turn_when_playing_against_itself() {
+        let mut env = Env::self_play(EnvOptions::default());
+        assert_eq!(&[Side::Top, Side::Bottom], env.agents());
+        let steps = play(&mut env, 3, Move::Left);
+        for step in &steps {
+            assert_eq!(0.0, step.rewards[0] + step.rewards[1]);
+        }
+        let last = steps.last().unwrap();
+        assert_eq!(
+            Some(MatchRules::default().points),
+            last.info.scores.iter().max().cloned()
+        );
+    }
+}
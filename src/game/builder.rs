@@ -0,0 +1,984 @@
+use super::components::*;
+use super::input::{Action, Binding, InputConfig, InputKind, Rebinding};
+use super::render::{BuildScene, DrawCommand, Render, Renderer, Scene, Viewport};
+use super::replay::ReplaySettings;
+use super::resources::{
+    BallSettings, BounceSettings, DeltaTime, GameArea, InputEvent, InputEvents, MatchRules,
+    MatchStatus, PaddleSettings, Random, RenderEvent, Tick,
+};
+use super::state::{Gated, State, StateHooks, StateMachine};
+use super::systems::*;
+use super::timestep::DEFAULT_TICK_RATE;
+use crate::math::Vector;
+use piston::input::Button;
+use serde_derive::{Deserialize, Serialize};
+use specs::prelude::*;
+
+/// What moves a paddle.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Controller {
+    /// A local player, using the bindings of its side in the `InputConfig`.
+    Keyboard,
+    /// A local player whose paddle follows the mouse cursor.
+    Mouse,
+    Ai(Difficulty),
+    /// A program, such as a learning agent, commanding the paddle through `Game::command`.
+    Agent,
+}
+
+/// A player and the side of the arena it defends.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Player {
+    pub side: Side,
+    pub controller: Controller,
+}
+
+/// A goal of the arena. A ball entering `zone` gives a point to the player on the `scorer`
+/// side and takes a life from the player on the `defender` side. Sides without a player are
+/// ignored, like the scorer of the default goals.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GoalZone {
+    pub zone: Zone,
+    #[serde(default)]
+    pub scorer: Option<Side>,
+    #[serde(default)]
+    pub defender: Option<Side>,
+}
+
+/// Goals of a classic game: getting the ball past an edge scores for the opposite player.
+pub fn default_goals(players: &[Player]) -> Vec<GoalZone> {
+    players
+        .iter()
+        .map(|player| GoalZone {
+            zone: Zone::Edge(player.side),
+            scorer: Some(player.side.opposite())
+                .filter(|side| players.iter().any(|p| p.side == *side)),
+            defender: None,
+        })
+        .collect()
+}
+
+/// Goals of a game played with lives: a player loses one when the ball gets past its edge.
+pub fn survival_goals(players: &[Player]) -> Vec<GoalZone> {
+    players
+        .iter()
+        .map(|player| GoalZone {
+            zone: Zone::Edge(player.side),
+            scorer: None,
+            defender: Some(player.side),
+        })
+        .collect()
+}
+
+/// Configures and creates a game: its world, entities and systems.
+pub struct GameBuilder<'a, 'b> {
+    seed: Option<u64>,
+    tick_rate: u32,
+    area: GameArea,
+    players: Vec<Player>,
+    lives: Option<u32>,
+    goals: Option<Vec<GoalZone>>,
+    rules: MatchRules,
+    ball: BallSettings,
+    paddle: PaddleSettings,
+    bounce: BounceSettings,
+    bindings: InputConfig,
+    menu: bool,
+    hooks: StateHooks,
+    renderer: Option<Dispatcher<'a, 'b>>,
+}
+
+impl<'a, 'b> GameBuilder<'a, 'b> {
+    /// Starts with a random seed, two keyboard players and no rendering.
+    pub fn new() -> Self {
+        GameBuilder {
+            seed: None,
+            tick_rate: DEFAULT_TICK_RATE,
+            area: GameArea {
+                width: 200.0,
+                height: 300.0,
+            },
+            players: vec![
+                Player {
+                    side: Side::Top,
+                    controller: Controller::Keyboard,
+                },
+                Player {
+                    side: Side::Bottom,
+                    controller: Controller::Keyboard,
+                },
+            ],
+            lives: None,
+            goals: None,
+            rules: MatchRules::default(),
+            ball: BallSettings::default(),
+            paddle: PaddleSettings::default(),
+            bounce: BounceSettings::default(),
+            bindings: InputConfig::default(),
+            menu: false,
+            hooks: StateHooks::default(),
+            renderer: None,
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn tick_rate(mut self, tick_rate: u32) -> Self {
+        self.tick_rate = tick_rate;
+        self
+    }
+
+    /// Sets the size in pixels of the arena when it is rendered.
+    pub fn arena(mut self, width: f64, height: f64) -> Self {
+        self.area = GameArea { width, height };
+        self
+    }
+
+    /// Puts a player on `side`, replacing the one already there if any.
+    pub fn player(mut self, side: Side, controller: Controller) -> Self {
+        self.players.retain(|player| player.side != side);
+        self.players.push(Player { side, controller });
+        self
+    }
+
+    pub fn top_player(self, controller: Controller) -> Self {
+        self.player(Side::Top, controller)
+    }
+
+    pub fn bottom_player(self, controller: Controller) -> Self {
+        self.player(Side::Bottom, controller)
+    }
+
+    /// Leaves `side` without a player, it becomes a wall.
+    pub fn without_player(mut self, side: Side) -> Self {
+        self.players.retain(|player| player.side != side);
+        self
+    }
+
+    /// Gives `lives` lives to every player instead of counting points.
+    pub fn lives(mut self, lives: u32) -> Self {
+        self.lives = Some(lives);
+        self
+    }
+
+    /// Replaces the goals of the arena, which are otherwise deduced from the players.
+    pub fn goals(mut self, goals: Vec<GoalZone>) -> Self {
+        self.goals = Some(goals);
+        self
+    }
+
+    pub fn rules(mut self, rules: MatchRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    pub fn ball(mut self, ball: BallSettings) -> Self {
+        self.ball = ball;
+        self
+    }
+
+    pub fn paddle(mut self, paddle: PaddleSettings) -> Self {
+        self.paddle = paddle;
+        self
+    }
+
+    pub fn bounce(mut self, bounce: BounceSettings) -> Self {
+        self.bounce = bounce;
+        self
+    }
+
+    pub fn bindings(mut self, bindings: InputConfig) -> Self {
+        self.bindings = bindings;
+        self
+    }
+
+    /// Starts on the main menu instead of waiting for the first serve.
+    pub fn menu(mut self) -> Self {
+        self.menu = true;
+        self
+    }
+
+    /// Calls `hook` whenever the game enters `state`.
+    pub fn on_enter<F>(mut self, state: State, hook: F) -> Self
+    where
+        F: FnMut(&mut World) + 'static,
+    {
+        self.hooks.on_enter(state, Box::new(hook));
+        self
+    }
+
+    /// Calls `hook` whenever the game leaves `state`.
+    pub fn on_exit<F>(mut self, state: State, hook: F) -> Self
+    where
+        F: FnMut(&mut World) + 'static,
+    {
+        self.hooks.on_exit(state, Box::new(hook));
+        self
+    }
+
+    /// Applies the settings a replay was recorded with.
+    pub fn replay_settings(mut self, settings: &ReplaySettings) -> Self {
+        self.tick_rate = settings.tick_rate;
+        self.players = settings.players.clone();
+        self.lives = settings.lives;
+        self.rules = settings.rules;
+        self.ball = settings.ball;
+        self.paddle = settings.paddle;
+        self.bounce = settings.bounce;
+        self.bindings = settings.bindings.clone();
+        self
+    }
+
+    /// Draws the game with `renderer` whenever `Game::render` is called.
+    pub fn render<R>(mut self, renderer: R) -> Self
+    where
+        R: Renderer + 'b,
+    {
+        self.renderer = Some(
+            DispatcherBuilder::new()
+                .with_thread_local(BuildScene)
+                .with_thread_local(Render(renderer))
+                .build(),
+        );
+        self
+    }
+
+    pub fn build(self) -> Game<'a, 'b> {
+        let seed = self.seed.unwrap_or_else(rand::random);
+
+        let mut world = World::new();
+        world.add_resource(Random::new(seed));
+        world.add_resource(self.area);
+        world.add_resource(DeltaTime(1.0 / f64::from(self.tick_rate)));
+        world.add_resource(Tick::default());
+        world.add_resource(self.rules);
+        world.add_resource(self.ball);
+        world.add_resource(self.paddle);
+        world.add_resource(self.bounce);
+        world.add_resource(self.bindings);
+        world.add_resource(Rebinding::default());
+        world.add_resource(Scene::default());
+        world.add_resource(StateMachine::new(if self.menu {
+            State::Menu
+        } else {
+            State::Serving
+        }));
+
+        let mut dispatcher = DispatcherBuilder::new()
+            .with(Gated(InputUpdate), "input_update", &[])
+            .with(Gated(InputApply), "input_apply", &["input_update"])
+            .with(Gated(CursorControl), "cursor_control", &["input_update"])
+            .with(Gated(AiControl), "ai_control", &[])
+            .with(Gated(AgentControl), "agent_control", &[])
+            .with(Gated(ServeCountdown), "serve_countdown", &[])
+            .with(
+                Gated(Movement),
+                "movement",
+                &[
+                    "input_apply",
+                    "cursor_control",
+                    "ai_control",
+                    "agent_control",
+                ],
+            )
+            .with(Gated(OutOfBound), "oob", &["movement"])
+            .with(Gated(CollisionDetection), "collision_detection", &["oob"])
+            .with(
+                Gated(CollisionResolution),
+                "collision_resolution",
+                &["collision_detection"],
+            )
+            .with(
+                Gated(ScoreComputer),
+                "score_computer",
+                &["collision_resolution"],
+            )
+            .with(Gated(Referee), "referee", &["score_computer"])
+            .build();
+        dispatcher.setup(&mut world.res);
+
+        let mut renderer = self.renderer;
+        if let Some(ref mut renderer) = renderer {
+            renderer.setup(&mut world.res);
+        }
+
+        let players = self.players;
+        let lives = self.lives;
+        let goals = self.goals.unwrap_or_else(|| match lives {
+            Some(_) => survival_goals(&players),
+            None => default_goals(&players),
+        });
+        let setup = Setup {
+            players,
+            lives,
+            goals,
+        };
+        spawn_entities(&mut world, &setup);
+        prepare_serve(&mut world);
+
+        let mut hooks = StateHooks::default();
+        hooks.on_enter(State::Serving, Box::new(prepare_serve));
+        // leaving the game over screen starts a rematch with the same players
+        hooks.on_exit(
+            State::GameOver,
+            Box::new(move |world| {
+                world.delete_all();
+                spawn_entities(world, &setup);
+                *world.write_resource::<MatchStatus>() = MatchStatus::default();
+            }),
+        );
+        hooks.on_enter(
+            State::Rebinding,
+            Box::new(|world| {
+                let sides: Vec<Side> = {
+                    let paddles = world.read_storage::<Paddle>();
+                    let inputs = world.read_storage::<Input>();
+                    (&paddles, &inputs).join().map(|(p, _)| p.side).collect()
+                };
+                *world.write_resource::<Rebinding>() = Rebinding::new(&sides);
+            }),
+        );
+        hooks.extend(self.hooks);
+
+        Game {
+            world,
+            dispatcher,
+            renderer,
+            hooks,
+        }
+    }
+}
+
+impl<'a, 'b> Default for GameBuilder<'a, 'b> {
+    fn default() -> Self {
+        GameBuilder::new()
+    }
+}
+
+/// What is needed to create the entities of a game, kept to start a rematch.
+struct Setup {
+    players: Vec<Player>,
+    lives: Option<u32>,
+    goals: Vec<GoalZone>,
+}
+
+fn spawn_entities(world: &mut World, setup: &Setup) {
+    let ball = *world.read_resource::<BallSettings>();
+    let paddle = *world.read_resource::<PaddleSettings>();
+
+    // ball
+    world
+        .create_entity()
+        .with(Position::new(0.5, 0.5))
+        .with(Velocity {
+            direction: Default::default(),
+            speed: ball.speed,
+            max_speed: ball.max_speed,
+        })
+        .with(Ball)
+        .with(Shape::Circle {
+            radius: ball.radius,
+        })
+        .build();
+
+    // paddles
+    let mut paddles = Vec::new();
+    for player in &setup.players {
+        let (near, far) = (paddle.thickness * 0.5, 1.0 - paddle.thickness * 0.5);
+        let (position, counter) = match player.side {
+            Side::Top => (Vector::new(0.5, far), Vector::new(1.01, 0.95)),
+            Side::Bottom => (Vector::new(0.5, near), Vector::new(1.01, 0.05)),
+            Side::Left => (Vector::new(near, 0.5), Vector::new(1.01, 0.35)),
+            Side::Right => (Vector::new(far, 0.5), Vector::new(1.01, 0.65)),
+        };
+        let size = player.side.axis() * paddle.length + player.side.outward() * paddle.thickness;
+        let entity = world
+            .create_entity()
+            .with(Position::new(position.x, position.y))
+            .with(Velocity::new(Default::default(), paddle.max_speed))
+            .with(Paddle { side: player.side })
+            .with(Shape::Rectangle {
+                width: size.x.abs(),
+                height: size.y.abs(),
+            });
+        let entity = match setup.lives {
+            Some(lives) => entity.with(Lives::new(lives, counter)),
+            None => entity.with(Score::new(counter)),
+        };
+        paddles.push((
+            player.side,
+            with_controller(entity, player.controller).build(),
+        ));
+    }
+
+    let paddle_on = |side: Option<Side>| {
+        side.and_then(|side| {
+            paddles
+                .iter()
+                .find(|(s, _)| *s == side)
+                .map(|(_, paddle)| *paddle)
+        })
+    };
+    for goal in &setup.goals {
+        world
+            .create_entity()
+            .with(Goal {
+                zone: goal.zone,
+                scorer: paddle_on(goal.scorer),
+                defender: paddle_on(goal.defender),
+            })
+            .build();
+    }
+}
+
+fn with_controller(entity: EntityBuilder, controller: Controller) -> EntityBuilder {
+    match controller {
+        Controller::Keyboard => entity.with(Input::default()),
+        Controller::Mouse => entity.with(FollowCursor),
+        Controller::Ai(difficulty) => entity.with(Ai::new(difficulty)),
+        Controller::Agent => entity.with(Agent::default()),
+    }
+}
+
+/// A game ready to be played: its world, the systems simulating it and those drawing it.
+pub struct Game<'a, 'b> {
+    pub world: World,
+    pub dispatcher: Dispatcher<'a, 'b>,
+    pub renderer: Option<Dispatcher<'a, 'b>>,
+    hooks: StateHooks,
+}
+
+impl<'a, 'b> Game<'a, 'b> {
+    /// Number of ticks simulated so far, which is also the tick that will run next.
+    pub fn tick(&self) -> u64 {
+        self.world.read_resource::<Tick>().0
+    }
+
+    /// Hands an input over to the game.
+    ///
+    /// Live and replayed inputs both go through here so that they have the same effect.
+    pub fn apply_input(&mut self, kind: InputKind) {
+        if self.state() == State::Rebinding {
+            self.rebind(kind);
+            return;
+        }
+
+        let tick = self.tick();
+        self.world
+            .write_resource::<InputEvents>()
+            .0
+            .push(InputEvent { tick, kind });
+        if let InputKind::Release(button) = kind {
+            if let Some(action) = self.action_of(button) {
+                self.perform(action);
+            }
+        }
+    }
+
+    /// Performs an action of the game itself, such as serving or pausing.
+    pub fn perform(&mut self, action: Action) {
+        let machine = self.world.read_resource::<StateMachine>();
+        let manual_serve = self
+            .world
+            .read_resource::<MatchStatus>()
+            .countdown
+            .is_none();
+        match (action, machine.current()) {
+            (Action::Serve, State::Menu) | (Action::Serve, State::GameOver) => {
+                machine.request(State::Serving)
+            }
+            (Action::Serve, State::Serving) if manual_serve => machine.request(State::Running),
+            (Action::Pause, State::Serving)
+            | (Action::Pause, State::Running)
+            | (Action::Back, State::Serving)
+            | (Action::Back, State::Running) => machine.request(State::Paused),
+            (Action::Pause, State::Paused) => machine.request(machine.previous()),
+            (Action::Rebind, State::Menu) => machine.request(State::Rebinding),
+            _ => {}
+        }
+        drop(machine);
+        self.hooks.apply(&mut self.world);
+    }
+
+    /// Serves the ball right away from the menu or between points, without waiting for the
+    /// server or the countdown.
+    pub fn serve_now(&mut self) {
+        if let State::Menu | State::GameOver = self.state() {
+            self.perform(Action::Serve);
+        }
+        if self.state() == State::Serving {
+            self.world.write_resource::<MatchStatus>().countdown = None;
+            self.perform(Action::Serve);
+        }
+    }
+
+    /// Whether pressing `button` quits the game, which the back action does outside of a match
+    /// in progress and of the bindings screen.
+    pub fn quits_on(&self, button: Button) -> bool {
+        self.action_of(button) == Some(Action::Back)
+            && matches!(self.state(), State::Menu | State::Paused | State::GameOver)
+    }
+
+    /// The action of the game itself triggered by `button`, if any.
+    fn action_of(&self, button: Button) -> Option<Action> {
+        self.world
+            .read_resource::<InputConfig>()
+            .game
+            .action(Binding::Button(button))
+    }
+
+    /// Binds the next action of the bindings screen to the released key, mouse button or the
+    /// mouse movement, the back action leaving the screen.
+    fn rebind(&mut self, kind: InputKind) {
+        let binding = match kind {
+            InputKind::Release(button) if self.action_of(button) == Some(Action::Back) => None,
+            InputKind::Release(button @ Button::Keyboard(_))
+            | InputKind::Release(button @ Button::Mouse(_)) => Some(Binding::Button(button)),
+            InputKind::Release(_) => return,
+            InputKind::MouseMotion(dx, dy) => match Binding::from_motion(dx, dy) {
+                Some(binding) => Some(binding),
+                None => return,
+            },
+            InputKind::Press(_) | InputKind::MouseCursor(..) => return,
+        };
+
+        let mut rebinding = self.world.write_resource::<Rebinding>();
+        if let (Some(binding), Some((owner, action))) = (binding, rebinding.next_action()) {
+            self.world
+                .write_resource::<InputConfig>()
+                .bind(owner, action, binding);
+            rebinding.current += 1;
+        }
+        if binding.is_none() || rebinding.next_action().is_none() {
+            self.world
+                .read_resource::<StateMachine>()
+                .request(State::Menu);
+        }
+        drop(rebinding);
+        self.hooks.apply(&mut self.world);
+    }
+
+    /// Runs one fixed tick of simulation.
+    pub fn step(&mut self) {
+        self.dispatcher.dispatch(&self.world.res);
+        self.world.maintain();
+        self.world.write_resource::<Tick>().0 += 1;
+        self.hooks.apply(&mut self.world);
+    }
+
+    /// Tells the agent on `side` where to move its paddle, until the next command. Paddles
+    /// without an agent ignore it.
+    pub fn command(&mut self, side: Side, command: Move) {
+        let paddles = self.world.read_storage::<Paddle>();
+        let mut agents = self.world.write_storage::<Agent>();
+        for (paddle, agent) in (&paddles, &mut agents).join() {
+            if paddle.side == side {
+                agent.command = command;
+            }
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.world.read_resource::<StateMachine>().current()
+    }
+
+    /// Draws the game if it was built with a renderer.
+    pub fn render(&mut self, viewport: Viewport) {
+        if let Some(ref mut renderer) = self.renderer {
+            *self.world.write_resource::<RenderEvent>() = RenderEvent(Some(viewport));
+            renderer.dispatch(&self.world.res);
+        }
+    }
+
+    /// Describes what a renderer would draw for the current state of the game.
+    pub fn scene(&self) -> Vec<DrawCommand> {
+        BuildScene.run_now(&self.world.res);
+        std::mem::take(&mut self.world.write_resource::<Scene>().0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::resources::ServeRule;
+    use crate::math::Rectangle;
+    use piston::input::Key;
+
+    /// Releases the ball, or waits for the countdown, until it is in play.
+    fn serve(game: &mut Game) {
+        game.apply_input(InputKind::Release(Button::Keyboard(Key::Space)));
+        while game.state() == State::Serving {
+            game.step();
+        }
+    }
+
+    #[test]
+    fn it_should_create_a_ball_and_two_paddles() {
+        let game = GameBuilder::new()
+            .seed(3)
+            .top_player(Controller::Ai(Difficulty::Hard))
+            .build();
+        let balls = game.world.read_storage::<Ball>();
+        let paddles = game.world.read_storage::<Paddle>();
+        assert_eq!(1, (&balls).join().count());
+        assert_eq!(2, (&paddles).join().count());
+    }
+
+    #[test]
+    fn it_should_bounce_following_the_chosen_settings() {
+        let bounce = BounceSettings {
+            max_angle: 0.5,
+            paddle_influence: 0.0,
+        };
+        let game = GameBuilder::new().bounce(bounce).build();
+        assert_eq!(bounce, *game.world.read_resource::<BounceSettings>());
+    }
+
+    #[test]
+    fn it_should_attach_the_chosen_controllers() {
+        let game = GameBuilder::new()
+            .top_player(Controller::Ai(Difficulty::Hard))
+            .build();
+        let positions = game.world.read_storage::<Position>();
+        let ais = game.world.read_storage::<Ai>();
+        let inputs = game.world.read_storage::<Input>();
+        let ai_paddles: Vec<f64> = (&positions, &ais)
+            .join()
+            .map(|(p, _)| p.default.y)
+            .collect();
+        let keyboard_paddles: Vec<f64> = (&positions, &inputs)
+            .join()
+            .map(|(p, _)| p.default.y)
+            .collect();
+        assert_eq!(vec![0.975], ai_paddles);
+        assert_eq!(vec![0.025], keyboard_paddles);
+    }
+
+    #[test]
+    fn it_should_score_in_custom_goal_zones() {
+        let mut game = GameBuilder::new()
+            .goals(vec![GoalZone {
+                zone: Zone::Area(Rectangle::new(Vector::new(0.4, 0.4), Vector::new(0.6, 0.6))),
+                scorer: Some(Side::Bottom),
+                defender: None,
+            }])
+            .build();
+        serve(&mut game);
+        game.step();
+
+        let positions = game.world.read_storage::<Position>();
+        let scores = game.world.read_storage::<Score>();
+        for (position, score) in (&positions, &scores).join() {
+            let expected = if position.default.y < 0.5 { 1 } else { 0 };
+            assert_eq!(expected, score.current);
+        }
+    }
+
+    #[test]
+    fn it_should_ignore_goal_sides_without_player() {
+        let mut game = GameBuilder::new()
+            .goals(vec![GoalZone {
+                zone: Zone::Area(Rectangle::new(Vector::new(0.4, 0.4), Vector::new(0.6, 0.6))),
+                scorer: Some(Side::Left),
+                defender: Some(Side::Right),
+            }])
+            .build();
+        {
+            let goals = game.world.read_storage::<Goal>();
+            assert!((&goals)
+                .join()
+                .all(|goal| goal.scorer.is_none() && goal.defender.is_none()));
+        }
+        serve(&mut game);
+        game.step();
+
+        let scores = game.world.read_storage::<Score>();
+        assert!((&scores).join().all(|score| score.current == 0));
+    }
+
+    #[test]
+    fn it_should_turn_sides_without_player_into_walls() {
+        let game = GameBuilder::new()
+            .player(Side::Left, Controller::Ai(Difficulty::Easy))
+            .player(Side::Right, Controller::Ai(Difficulty::Easy))
+            .without_player(Side::Top)
+            .lives(3)
+            .build();
+        let goals = game.world.read_storage::<Goal>();
+        let mut edges: Vec<Zone> = (&goals).join().map(|goal| goal.zone).collect();
+        edges.sort_by_key(|zone| format!("{:?}", zone));
+        assert_eq!(
+            vec![
+                Zone::Edge(Side::Bottom),
+                Zone::Edge(Side::Left),
+                Zone::Edge(Side::Right)
+            ],
+            edges
+        );
+        assert!((&goals)
+            .join()
+            .all(|goal| goal.scorer.is_none() && goal.defender.is_some()));
+    }
+
+    #[test]
+    fn it_should_eliminate_players_out_of_lives() {
+        let mut game = GameBuilder::new()
+            .seed(1)
+            .top_player(Controller::Ai(Difficulty::Easy))
+            .bottom_player(Controller::Ai(Difficulty::Easy))
+            .player(Side::Left, Controller::Ai(Difficulty::Easy))
+            .player(Side::Right, Controller::Ai(Difficulty::Easy))
+            .lives(1)
+            .build();
+        for _ in 0..120 * 120 {
+            if game.state() == State::Serving {
+                game.apply_input(InputKind::Release(Button::Keyboard(Key::Space)));
+            }
+            game.step();
+        }
+        let paddles = game.world.read_storage::<Paddle>();
+        let goals = game.world.read_storage::<Goal>();
+        assert!((&paddles).join().count() < 4);
+        assert_eq!((&paddles).join().count(), (&goals).join().count());
+    }
+
+    #[test]
+    fn it_should_end_the_match_after_enough_sets_and_offer_a_rematch() {
+        let mut game = GameBuilder::new()
+            .rules(MatchRules {
+                points: 1,
+                best_of: 3,
+                ..Default::default()
+            })
+            .goals(vec![GoalZone {
+                zone: Zone::Area(Rectangle::new(Vector::new(0.4, 0.4), Vector::new(0.6, 0.6))),
+                scorer: Some(Side::Bottom),
+                defender: None,
+            }])
+            .build();
+        let sets = |game: &Game| -> Vec<(u32, u32)> {
+            let scores = game.world.read_storage::<Score>();
+            (&scores).join().map(|s| (s.current, s.sets)).collect()
+        };
+
+        serve(&mut game);
+        game.step();
+        assert_eq!(State::Serving, game.state());
+        assert!(sets(&game).contains(&(0, 1)));
+
+        serve(&mut game);
+        game.step();
+        assert_eq!(State::GameOver, game.state());
+        assert_eq!(
+            Some(Side::Bottom),
+            game.world.read_resource::<MatchStatus>().winner
+        );
+
+        game.apply_input(InputKind::Release(Button::Keyboard(Key::Space)));
+        assert_eq!(State::Serving, game.state());
+        assert_eq!(vec![(0, 0), (0, 0)], sets(&game));
+        assert_eq!(None, game.world.read_resource::<MatchStatus>().winner);
+    }
+
+    #[test]
+    fn it_should_freeze_the_game_while_paused() {
+        let mut game = GameBuilder::new().seed(5).build();
+        let positions = |game: &Game| -> Vec<Vector> {
+            let positions = game.world.read_storage::<Position>();
+            (&positions).join().map(|p| p.current).collect()
+        };
+        serve(&mut game);
+        game.apply_input(InputKind::Press(Button::Keyboard(Key::Left)));
+        game.step();
+
+        game.apply_input(InputKind::Release(Button::Keyboard(Key::P)));
+        assert_eq!(State::Paused, game.state());
+        let frozen = positions(&game);
+        for _ in 0..10 {
+            game.step();
+        }
+        assert_eq!(frozen, positions(&game));
+        assert!((&game.world.read_storage::<Input>())
+            .join()
+            .any(|input| input.left));
+
+        game.apply_input(InputKind::Release(Button::Keyboard(Key::P)));
+        assert_eq!(State::Running, game.state());
+        game.step();
+        assert_ne!(frozen, positions(&game));
+    }
+
+    #[test]
+    fn it_should_serve_away_from_the_loser() {
+        let mut game = GameBuilder::new()
+            .seed(2)
+            .rules(MatchRules {
+                serve: ServeRule::LoserServes,
+                manual_serve: true,
+                ..Default::default()
+            })
+            .top_player(Controller::Ai(Difficulty::Hard))
+            .build();
+        serve(&mut game);
+        while game.state() == State::Running {
+            game.step();
+        }
+
+        let status = game.world.read_resource::<MatchStatus>();
+        let loser = status.server.unwrap();
+        let velocities = game.world.read_storage::<Velocity>();
+        let balls = game.world.read_storage::<Ball>();
+        let (velocity, _) = (&velocities, &balls).join().next().unwrap();
+        assert!(velocity.direction.dot(loser.outward()) < 0.0);
+        // only a keyboard player releases the ball by hand
+        assert_eq!(loser == Side::Bottom, status.countdown.is_none());
+    }
+
+    #[test]
+    fn it_should_handle_every_input_received_between_ticks() {
+        let mut game = GameBuilder::new().seed(4).build();
+        serve(&mut game);
+        let held = |game: &Game| -> Vec<bool> {
+            let inputs = game.world.read_storage::<Input>();
+            (&inputs).join().map(|input| input.left).collect()
+        };
+
+        game.apply_input(InputKind::Press(Button::Keyboard(Key::Q)));
+        game.apply_input(InputKind::Press(Button::Keyboard(Key::Left)));
+        game.step();
+        assert_eq!(vec![true, true], held(&game));
+
+        game.apply_input(InputKind::Release(Button::Keyboard(Key::Q)));
+        game.apply_input(InputKind::Release(Button::Keyboard(Key::Left)));
+        game.step();
+        assert_eq!(vec![false, false], held(&game));
+    }
+
+    #[test]
+    fn it_should_not_keep_inputs_received_before_the_serve() {
+        let mut game = GameBuilder::new().seed(4).menu().build();
+        game.apply_input(InputKind::Press(Button::Keyboard(Key::Q)));
+        game.apply_input(InputKind::MouseMotion(5.0, 0.0));
+        game.step();
+        assert!(game.world.read_resource::<InputEvents>().0.is_empty());
+
+        game.apply_input(InputKind::Release(Button::Keyboard(Key::Q)));
+        game.apply_input(InputKind::Press(Button::Keyboard(Key::Left)));
+        game.apply_input(InputKind::Release(Button::Keyboard(Key::Space)));
+        game.step();
+        assert_eq!(State::Serving, game.state());
+        assert!(game.world.read_resource::<InputEvents>().0.is_empty());
+
+        serve(&mut game);
+        game.step();
+        let inputs = game.world.read_storage::<Input>();
+        let paddles = game.world.read_storage::<Paddle>();
+        for (input, paddle) in (&inputs, &paddles).join() {
+            // Q was released in the menu while Left is still held
+            assert_eq!(paddle.side == Side::Bottom, input.left);
+        }
+    }
+
+    #[test]
+    fn it_should_move_with_the_new_bindings() {
+        let mut game = GameBuilder::new().menu().build();
+        let release =
+            |game: &mut Game, key| game.apply_input(InputKind::Release(Button::Keyboard(key)));
+        release(&mut game, Key::B);
+        assert_eq!(State::Rebinding, game.state());
+        // top player moving left with A, then Esc keeps the remaining bindings
+        release(&mut game, Key::A);
+        release(&mut game, Key::Escape);
+        assert_eq!(State::Menu, game.state());
+
+        release(&mut game, Key::Space);
+        serve(&mut game);
+        game.apply_input(InputKind::Press(Button::Keyboard(Key::A)));
+        game.step();
+        let inputs = game.world.read_storage::<Input>();
+        let paddles = game.world.read_storage::<Paddle>();
+        let (input, _) = (&inputs, &paddles)
+            .join()
+            .find(|(_, paddle)| paddle.side == Side::Top)
+            .unwrap();
+        assert!(input.left);
+    }
+
+    #[test]
+    fn it_should_leave_screens_with_the_back_binding() {
+        let mut game = GameBuilder::new().menu().build();
+        let x = Button::Keyboard(Key::X);
+        game.world
+            .write_resource::<InputConfig>()
+            .bind(None, Action::Back, Binding::Button(x));
+        assert!(game.quits_on(x));
+        assert!(!game.quits_on(Button::Keyboard(Key::Escape)));
+
+        game.apply_input(InputKind::Release(Button::Keyboard(Key::B)));
+        assert_eq!(State::Rebinding, game.state());
+        assert!(!game.quits_on(x));
+        game.apply_input(InputKind::Release(x));
+        assert_eq!(State::Menu, game.state());
+    }
+
+    #[test]
+    fn it_should_follow_the_cursor_at_max_speed() {
+        let mut game = GameBuilder::new()
+            .seed(5)
+            .bottom_player(Controller::Mouse)
+            .build();
+        serve(&mut game);
+        let paddle_x = |game: &Game| -> f64 {
+            let positions = game.world.read_storage::<Position>();
+            let paddles = game.world.read_storage::<Paddle>();
+            (&positions, &paddles)
+                .join()
+                .find(|(_, paddle)| paddle.side == Side::Bottom)
+                .map(|(position, _)| position.current.x)
+                .unwrap()
+        };
+
+        // the default arena is 200 pixels wide
+        game.apply_input(InputKind::MouseCursor(140.0, 290.0));
+        let start = paddle_x(&game);
+        game.step();
+        let delta = game.world.read_resource::<DeltaTime>().0;
+        assert!((paddle_x(&game) - start - 0.55 * delta).abs() < 1e-9);
+
+        for _ in 0..60 {
+            game.step();
+        }
+        assert!((paddle_x(&game) - 0.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_should_move_agents_as_commanded() {
+        let mut game = GameBuilder::new()
+            .seed(5)
+            .top_player(Controller::Agent)
+            .build();
+        serve(&mut game);
+        let velocity = |game: &Game| -> Vector {
+            let velocities = game.world.read_storage::<Velocity>();
+            let paddles = game.world.read_storage::<Paddle>();
+            (&velocities, &paddles)
+                .join()
+                .find(|(_, paddle)| paddle.side == Side::Top)
+                .map(|(velocity, _)| velocity.direction)
+                .unwrap()
+        };
+
+        game.command(Side::Top, Move::Left);
+        game.step();
+        assert_eq!(Vector::new(-1.0, 0.0), velocity(&game));
+        // commands hold until the next one
+        game.step();
+        assert_eq!(Vector::new(-1.0, 0.0), velocity(&game));
+        game.command(Side::Top, Move::Stay);
+        game.step();
+        assert_eq!(Vector::default(), velocity(&game));
+    }
+}
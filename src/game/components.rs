@@ -1,12 +1,15 @@
-use crate::math::Vector;
-use piston::input::Key;
+use crate::math::{Contact, Rectangle, Vector};
+use piston::input::Button;
+use serde_derive::{Deserialize, Serialize};
 use specs::prelude::*;
 use specs_derive::*;
+use std::str::FromStr;
 
 #[derive(Component)]
 #[storage(VecStorage)]
 pub struct Position {
     pub current: Vector,
+    pub previous: Vector,
     pub default: Vector,
 }
 
@@ -14,9 +17,15 @@ impl Position {
     pub fn new(x: f64, y: f64) -> Self {
         Position {
             current: Vector::new(x, y),
+            previous: Vector::new(x, y),
             default: Vector::new(x, y),
         }
     }
+
+    pub fn reset(&mut self) {
+        self.current = self.default;
+        self.previous = self.default;
+    }
 }
 
 #[derive(Component)]
@@ -41,9 +50,12 @@ impl Velocity {
 #[storage(NullStorage)]
 pub struct Ball;
 
-#[derive(Component, Default)]
-#[storage(NullStorage)]
-pub struct Paddle;
+/// A paddle defending `side`. It slides along that side of the arena.
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct Paddle {
+    pub side: Side,
+}
 
 #[derive(Component)]
 #[storage(VecStorage)]
@@ -52,26 +64,112 @@ pub enum Shape {
     Rectangle { width: f64, height: f64 },
 }
 
-#[derive(Component, Default)]
-#[storage(NullStorage)]
-pub struct Collision;
-
+/// Marks a ball that hit `paddle` during the last frame.
+///
+/// `time` is the fraction of the frame (between 0 and 1) at which the contact happened.
 #[derive(Component)]
 #[storage(VecStorage)]
+pub struct Collision {
+    pub paddle: Entity,
+    pub time: f64,
+    pub contact: Contact,
+}
+
+/// Movement asked by the player controlling a paddle, following the bindings of its side.
+#[derive(Component, Default)]
+#[storage(VecStorage)]
 pub struct Input {
-    pub key_right: Key,
-    pub key_left: Key,
+    /// Buttons currently held down.
+    pub held: Vec<Button>,
     pub right: bool,
     pub left: bool,
 }
 
-impl Input {
-    pub fn new(key_left: Key, key_right: Key) -> Input {
-        Input {
-            key_right,
-            key_left,
-            right: false,
-            left: false,
+/// Direction a program asks its paddle to move in. Right moves up on vertical paddles.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Move {
+    Left,
+    #[default]
+    Stay,
+    Right,
+}
+
+/// A paddle moved by a program, such as a learning agent, through `Game::command`.
+#[derive(Component, Default)]
+#[storage(VecStorage)]
+pub struct Agent {
+    pub command: Move,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// Seconds between two looks at the ball.
+    pub fn reaction_delay(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.35,
+            Difficulty::Normal => 0.2,
+            Difficulty::Hard => 0.08,
+        }
+    }
+
+    /// Largest distance between the predicted and the actual intercept point.
+    pub fn prediction_error(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.12,
+            Difficulty::Normal => 0.06,
+            Difficulty::Hard => 0.015,
+        }
+    }
+
+    /// Fraction of the paddle's max speed the AI is allowed to use.
+    pub fn speed_factor(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.6,
+            Difficulty::Normal => 0.8,
+            Difficulty::Hard => 1.0,
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("Unknown difficulty '{}'", s)),
+        }
+    }
+}
+
+/// Marks a paddle following the mouse cursor along its axis.
+#[derive(Component, Default)]
+#[storage(NullStorage)]
+pub struct FollowCursor;
+
+/// Drives a paddle towards where it expects the ball to cross its line.
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct Ai {
+    pub difficulty: Difficulty,
+    pub target: Option<f64>,
+    pub cooldown: f64,
+}
+
+impl Ai {
+    pub fn new(difficulty: Difficulty) -> Ai {
+        Ai {
+            difficulty,
+            target: None,
+            cooldown: 0.0,
         }
     }
 }
@@ -79,17 +177,118 @@ impl Input {
 #[derive(Component)]
 #[storage(VecStorage)]
 pub struct Score {
+    /// Points in the current set.
     pub current: u32,
-    pub trigger: fn(Vector) -> bool,
+    /// Sets won so far.
+    pub sets: u32,
     pub position: Vector,
 }
 
 impl Score {
-    pub fn new(trigger: fn(Vector) -> bool, position: Vector) -> Score {
+    pub fn new(position: Vector) -> Score {
         Score {
             current: 0,
-            trigger,
+            sets: 0,
             position,
         }
     }
 }
+
+/// One of the four edges of the arena.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Side {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Side {
+    pub fn all() -> [Side; 4] {
+        [Side::Top, Side::Bottom, Side::Left, Side::Right]
+    }
+
+    /// Unit vector along which a paddle defending this side moves.
+    pub fn axis(self) -> Vector {
+        match self {
+            Side::Top | Side::Bottom => Vector::new(1.0, 0.0),
+            Side::Left | Side::Right => Vector::new(0.0, 1.0),
+        }
+    }
+
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Top => Side::Bottom,
+            Side::Bottom => Side::Top,
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        }
+    }
+
+    /// Unit vector perpendicular to `axis`, pointing away from the arena's center.
+    pub fn outward(self) -> Vector {
+        match self {
+            Side::Top => Vector::new(0.0, 1.0),
+            Side::Bottom => Vector::new(0.0, -1.0),
+            Side::Left => Vector::new(-1.0, 0.0),
+            Side::Right => Vector::new(1.0, 0.0),
+        }
+    }
+}
+
+impl FromStr for Side {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top" => Ok(Side::Top),
+            "bottom" => Ok(Side::Bottom),
+            "left" => Ok(Side::Left),
+            "right" => Ok(Side::Right),
+            _ => Err(format!("Unknown side '{}'", s)),
+        }
+    }
+}
+
+/// A region of the arena, in normalized coordinates.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Zone {
+    /// Everything beyond one of the arena's edges.
+    Edge(Side),
+    Area(Rectangle),
+}
+
+impl Zone {
+    pub fn contains(self, point: Vector) -> bool {
+        match self {
+            Zone::Edge(Side::Top) => point.y > 1.0,
+            Zone::Edge(Side::Bottom) => point.y < 0.0,
+            Zone::Edge(Side::Left) => point.x < 0.0,
+            Zone::Edge(Side::Right) => point.x > 1.0,
+            Zone::Area(rectangle) => rectangle.contains(point),
+        }
+    }
+}
+
+/// Gives a point to `scorer` and takes a life from `defender` when a ball enters `zone`.
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct Goal {
+    pub zone: Zone,
+    pub scorer: Option<Entity>,
+    pub defender: Option<Entity>,
+}
+
+/// Lives left to a player. The player is out of the game once none is left.
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct Lives {
+    pub current: u32,
+    pub position: Vector,
+}
+
+impl Lives {
+    pub fn new(current: u32, position: Vector) -> Lives {
+        Lives { current, position }
+    }
+}
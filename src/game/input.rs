@@ -0,0 +1,441 @@
+//! Actions performed by the players and what triggers them.
+
+use super::components::Side;
+use piston::input::{Button, Key, MouseButton};
+use serde::de::{self, DeserializeOwned, Deserializer, IntoDeserializer};
+use serde::ser::{self, Serializer};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Pixels the mouse must travel in one event to be bound on the bindings screen.
+const MOUSE_BINDING_THRESHOLD: f64 = 5.0;
+
+/// Something a player did with a device.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum InputKind {
+    Press(Button),
+    Release(Button),
+    /// The mouse moved by the given number of pixels.
+    MouseMotion(f64, f64),
+    /// The mouse cursor moved to the given position in the window, in pixels.
+    MouseCursor(f64, f64),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    /// Moves a paddle left, or down for vertical paddles.
+    MoveLeft,
+    /// Moves a paddle right, or up for vertical paddles.
+    MoveRight,
+    Serve,
+    Pause,
+    /// Opens the bindings screen from the main menu.
+    Rebind,
+    /// Leaves the current screen: pauses a match in progress, finishes the bindings screen and
+    /// quits from the other screens.
+    Back,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MouseAxis {
+    X,
+    Y,
+}
+
+/// Something triggering an action, saved as the text it is displayed with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Binding {
+    /// Held while the button is down.
+    Button(Button),
+    /// Held during the ticks in which the mouse moves along `axis`, towards positive
+    /// coordinates if `positive`.
+    Mouse { axis: MouseAxis, positive: bool },
+}
+
+impl Binding {
+    /// Returns the mouse binding matching a movement of the mouse, if it is long enough.
+    pub fn from_motion(dx: f64, dy: f64) -> Option<Self> {
+        let (axis, delta) = if dx.abs() >= dy.abs() {
+            (MouseAxis::X, dx)
+        } else {
+            (MouseAxis::Y, dy)
+        };
+        if delta.abs() < MOUSE_BINDING_THRESHOLD {
+            return None;
+        }
+        Some(Binding::Mouse {
+            axis,
+            positive: delta > 0.0,
+        })
+    }
+
+    /// Whether a mouse movement of `dx` and `dy` triggers this binding.
+    pub fn matches_motion(&self, dx: f64, dy: f64) -> bool {
+        match *self {
+            Binding::Button(_) => false,
+            Binding::Mouse { axis, positive } => {
+                let delta = match axis {
+                    MouseAxis::X => dx,
+                    MouseAxis::Y => dy,
+                };
+                if positive {
+                    delta > 0.0
+                } else {
+                    delta < 0.0
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Button(Button::Keyboard(key)) => write!(f, "{:?}", key),
+            Binding::Button(Button::Mouse(button)) => write!(f, "Mouse {:?}", button),
+            Binding::Button(button) => write!(f, "{:?}", button),
+            Binding::Mouse { axis, positive } => {
+                write!(f, "Mouse {:?}{}", axis, if *positive { "+" } else { "-" })
+            }
+        }
+    }
+}
+
+impl FromStr for Binding {
+    type Err = String;
+
+    /// Reads a binding of the keyboard or of the mouse written as it is displayed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mouse = |axis, positive| Some(Binding::Mouse { axis, positive });
+        let binding = match s.strip_prefix("Mouse ") {
+            Some("X+") => mouse(MouseAxis::X, true),
+            Some("X-") => mouse(MouseAxis::X, false),
+            Some("Y+") => mouse(MouseAxis::Y, true),
+            Some("Y-") => mouse(MouseAxis::Y, false),
+            Some(button) => variant(button).map(|button| Binding::Button(Button::Mouse(button))),
+            None => variant(s).map(|key| Binding::Button(Button::Keyboard(key))),
+        };
+        binding.ok_or_else(|| format!("Unknown binding '{}'", s))
+    }
+}
+
+impl serde::Serialize for Binding {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Binding::Button(Button::Keyboard(_))
+            | Binding::Button(Button::Mouse(_))
+            | Binding::Mouse { .. } => serializer.collect_str(self),
+            _ => Err(ser::Error::custom(format!(
+                "Cannot save the binding {}",
+                self
+            ))),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Binding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(de::Error::custom)
+    }
+}
+
+/// The variant of a fieldless enum named `name`.
+fn variant<T: DeserializeOwned>(name: &str) -> Option<T> {
+    T::deserialize(IntoDeserializer::<de::value::Error>::into_deserializer(
+        name,
+    ))
+    .ok()
+}
+
+/// What triggers each action. An action can have several bindings.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Bindings(#[serde(with = "enum_keys")] pub BTreeMap<Action, Vec<Binding>>);
+
+impl Bindings {
+    pub fn new(bindings: &[(Action, Binding)]) -> Self {
+        let mut result = Bindings::default();
+        for (action, binding) in bindings {
+            result.0.entry(*action).or_default().push(*binding);
+        }
+        result
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0
+            .get(&action)
+            .map_or(&[], |bindings| bindings.as_slice())
+    }
+
+    /// Returns the action triggered by `binding`, if any.
+    pub fn action(&self, binding: Binding) -> Option<Action> {
+        self.0
+            .iter()
+            .find(|(_, bindings)| bindings.contains(&binding))
+            .map(|(action, _)| *action)
+    }
+}
+
+/// Who performs an action: a player or, for actions such as pausing, the whole game.
+pub type Owner = Option<Side>;
+
+/// A binding triggering two different actions.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Conflict {
+    pub binding: Binding,
+    pub first: (Owner, Action),
+    pub second: (Owner, Action),
+}
+
+/// Bindings of every player and of the game itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputConfig {
+    #[serde(with = "enum_keys")]
+    pub players: HashMap<Side, Bindings>,
+    pub game: Bindings,
+}
+
+impl InputConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        InputConfig::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Reads bindings from TOML, like the settings of the config file.
+    pub fn parse(source: &str) -> io::Result<Self> {
+        toml::from_str(source)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// Reads the bindings from `path`, keeping the default ones when the file is missing since
+    /// they are saved there.
+    pub fn load_or_default(path: &str) -> Result<Self, String> {
+        if !Path::new(path).exists() {
+            return Ok(InputConfig::default());
+        }
+        InputConfig::load(path).map_err(|e| format!("Failed to load bindings '{}': {}", path, e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let source = toml::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        fs::write(path, source)
+    }
+
+    pub fn bindings(&self, owner: Owner) -> Option<&Bindings> {
+        match owner {
+            Some(side) => self.players.get(&side),
+            None => Some(&self.game),
+        }
+    }
+
+    /// Replaces the bindings of an action with a single one.
+    pub fn bind(&mut self, owner: Owner, action: Action, binding: Binding) {
+        let bindings = match owner {
+            Some(side) => self.players.entry(side).or_default(),
+            None => &mut self.game,
+        };
+        bindings.0.insert(action, vec![binding]);
+    }
+
+    /// Lists the bindings triggering more than one action.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut sides: Vec<&Side> = self.players.keys().collect();
+        sides.sort_by_key(|side| Side::all().iter().position(|s| s == *side));
+        let mut actions: Vec<(Owner, Action, Binding)> = Vec::new();
+        for owner in sides.into_iter().map(|side| Some(*side)).chain(Some(None)) {
+            for (action, bindings) in &self.bindings(owner).unwrap().0 {
+                for binding in bindings {
+                    actions.push((owner, *action, *binding));
+                }
+            }
+        }
+
+        let mut conflicts = Vec::new();
+        for (i, first) in actions.iter().enumerate() {
+            for second in &actions[i + 1..] {
+                if first.2 == second.2 && (first.0, first.1) != (second.0, second.1) {
+                    conflicts.push(Conflict {
+                        binding: first.2,
+                        first: (first.0, first.1),
+                        second: (second.0, second.1),
+                    });
+                }
+            }
+        }
+        conflicts
+    }
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        let keys = |left, right| {
+            Bindings::new(&[
+                (Action::MoveLeft, Binding::Button(Button::Keyboard(left))),
+                (Action::MoveRight, Binding::Button(Button::Keyboard(right))),
+            ])
+        };
+        let mut players = HashMap::new();
+        players.insert(Side::Top, keys(Key::Q, Key::D));
+        players.insert(Side::Bottom, keys(Key::Left, Key::Right));
+        players.insert(Side::Left, keys(Key::S, Key::W));
+        players.insert(Side::Right, keys(Key::NumPad2, Key::NumPad8));
+        InputConfig {
+            players,
+            game: Bindings::new(&[
+                (Action::Serve, Binding::Button(Button::Keyboard(Key::Space))),
+                (
+                    Action::Serve,
+                    Binding::Button(Button::Mouse(MouseButton::Left)),
+                ),
+                (Action::Pause, Binding::Button(Button::Keyboard(Key::P))),
+                (Action::Rebind, Binding::Button(Button::Keyboard(Key::B))),
+                (Action::Back, Binding::Button(Button::Keyboard(Key::Escape))),
+            ]),
+        }
+    }
+}
+
+/// Writes maps keyed by enums with the names of the variants as keys, TOML only accepting
+/// strings as keys.
+mod enum_keys {
+    use serde::de::{Deserialize, Deserializer, IntoDeserializer};
+    use serde::ser::{self, Serialize, Serializer};
+    use std::collections::BTreeMap;
+    use std::iter::FromIterator;
+
+    pub fn serialize<'a, M, K, V, S>(map: &'a M, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        &'a M: IntoIterator<Item = (&'a K, &'a V)>,
+        K: Serialize + 'a,
+        V: Serialize + 'a,
+        S: Serializer,
+    {
+        let mut entries = BTreeMap::new();
+        for (key, value) in map {
+            match toml::Value::try_from(key).map_err(ser::Error::custom)? {
+                toml::Value::String(key) => entries.insert(key, value),
+                _ => return Err(ser::Error::custom("keys must be unit variants")),
+            };
+        }
+        entries.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, M, K, V, D>(deserializer: D) -> Result<M, D::Error>
+    where
+        M: FromIterator<(K, V)>,
+        K: Deserialize<'de>,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        BTreeMap::<String, V>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(key, value)| {
+                let key = IntoDeserializer::<D::Error>::into_deserializer(key.as_str());
+                Ok((K::deserialize(key)?, value))
+            })
+            .collect()
+    }
+}
+
+/// Progress through the bindings screen, which asks for a new binding for each action in turn.
+#[derive(Default)]
+pub struct Rebinding {
+    pub actions: Vec<(Owner, Action)>,
+    pub current: usize,
+}
+
+impl Rebinding {
+    /// Walks through the moves of the players on `sides`, then the serve and pause actions.
+    pub fn new(sides: &[Side]) -> Self {
+        let mut actions = Vec::new();
+        for side in sides {
+            actions.push((Some(*side), Action::MoveLeft));
+            actions.push((Some(*side), Action::MoveRight));
+        }
+        actions.push((None, Action::Serve));
+        actions.push((None, Action::Pause));
+        Rebinding {
+            actions,
+            current: 0,
+        }
+    }
+
+    pub fn next_action(&self) -> Option<(Owner, Action)> {
+        self.actions.get(self.current).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_not_have_conflicts_by_default() {
+        assert!(InputConfig::default().conflicts().is_empty());
+    }
+
+    #[test]
+    fn it_should_detect_a_binding_shared_by_two_players() {
+        let mut config = InputConfig::default();
+        let left = Binding::Button(Button::Keyboard(Key::Left));
+        config.bind(Some(Side::Top), Action::MoveRight, left);
+        assert_eq!(
+            vec![Conflict {
+                binding: left,
+                first: (Some(Side::Top), Action::MoveRight),
+                second: (Some(Side::Bottom), Action::MoveLeft),
+            }],
+            config.conflicts()
+        );
+    }
+
+    #[test]
+    fn it_should_find_the_action_of_any_of_its_bindings() {
+        let config = InputConfig::default();
+        let pause = |key| config.game.action(Binding::Button(Button::Keyboard(key)));
+        assert_eq!(Some(Action::Pause), pause(Key::P));
+        assert_eq!(Some(Action::Back), pause(Key::Escape));
+        assert_eq!(None, pause(Key::A));
+    }
+
+    #[test]
+    fn it_should_save_bindings_as_toml() {
+        let mut config = InputConfig::default();
+        config.bind(
+            Some(Side::Top),
+            Action::MoveLeft,
+            Binding::Mouse {
+                axis: MouseAxis::Y,
+                positive: true,
+            },
+        );
+        let source = toml::to_string_pretty(&config).unwrap();
+        assert!(source.contains("[players.Top]"));
+        assert_eq!(config, InputConfig::parse(&source).unwrap());
+
+        // replays hold their bindings as JSON
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(config, serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn it_should_bind_long_enough_mouse_motions() {
+        assert_eq!(None, Binding::from_motion(2.0, 1.0));
+        let left = Binding::from_motion(-8.0, 3.0).unwrap();
+        assert_eq!(
+            Binding::Mouse {
+                axis: MouseAxis::X,
+                positive: false
+            },
+            left
+        );
+        assert!(left.matches_motion(-1.0, 0.0));
+        assert!(!left.matches_motion(1.0, 0.0));
+    }
+}
@@ -1,15 +1,15 @@
-pub mod components; 
-pub mod systems;
+pub mod builder;
+pub mod components;
+pub mod input;
+pub mod opengl;
+pub mod raster;
+pub mod render;
+pub mod replay;
 pub mod resources;
+pub mod state;
+pub mod systems;
+pub mod terminal;
+pub mod timestep;
 
-#[derive(Eq, PartialEq)]
-pub enum State {
-    Idle,
-    Running,
-}
-
-impl Default for State {
-    fn default() -> Self {
-        State::Idle
-    }
-}
\ No newline at end of file
+pub use self::builder::{Controller, Game, GameBuilder, Player};
+pub use self::state::State;
@@ -0,0 +1,54 @@
+use super::render::{DrawCommand, Renderer, Viewport};
+use graphics::{clear, ellipse, rectangle, text, Transformed};
+use opengl_graphics::{GlGraphics, GlyphCache};
+
+/// Draws scenes in an OpenGL window.
+pub struct GlRenderer<'a> {
+    pub gl: GlGraphics,
+    pub glyphs: GlyphCache<'a>,
+}
+
+impl<'a> Renderer for GlRenderer<'a> {
+    fn draw(&mut self, viewport: Viewport, commands: &[DrawCommand]) {
+        let viewport = graphics::Viewport {
+            rect: [
+                0,
+                0,
+                viewport.draw_width as i32,
+                viewport.draw_height as i32,
+            ],
+            window_size: [viewport.width, viewport.height],
+            draw_size: [viewport.draw_width, viewport.draw_height],
+        };
+        let glyphs = &mut self.glyphs;
+        self.gl.draw(viewport, |context, graphics| {
+            for command in commands {
+                match command {
+                    DrawCommand::Clear(color) => clear(*color, graphics),
+                    DrawCommand::Rectangle { color, rect } => {
+                        rectangle(*color, *rect, context.transform, graphics)
+                    }
+                    DrawCommand::Circle {
+                        color,
+                        center,
+                        radius,
+                    } => ellipse(
+                        *color,
+                        [-radius, -radius, radius * 2.0, radius * 2.0],
+                        context.transform.trans(center[0], center[1]),
+                        graphics,
+                    ),
+                    DrawCommand::Text {
+                        color,
+                        size,
+                        position,
+                        text: line,
+                    } => {
+                        let transform = context.transform.trans(position[0], position[1]);
+                        text(*color, *size, line, glyphs, transform, graphics).unwrap_or(());
+                    }
+                }
+            }
+        });
+    }
+}
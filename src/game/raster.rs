@@ -0,0 +1,338 @@
+//! Software renderer drawing scenes into an RGBA buffer, for machines without a GPU.
+
+use super::render::{Color, DrawCommand, Renderer, Viewport};
+use png::HasParameters;
+use rusttype::{point, Font, FontCollection, Scale};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// An image made of RGBA pixels, row after row from the top left corner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Frame {
+    /// Creates a transparent frame.
+    pub fn new(width: u32, height: u32) -> Self {
+        Frame {
+            width,
+            height,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+
+    /// Paints `color` over the pixel, `coverage` being the fraction of the pixel covered.
+    fn blend(&mut self, x: i64, y: i64, color: Color, coverage: f32) {
+        if x < 0 || y < 0 || x >= i64::from(self.width) || y >= i64::from(self.height) {
+            return;
+        }
+        let alpha = color[3] * coverage;
+        let i = ((y as u32 * self.width + x as u32) * 4) as usize;
+        for (channel, value) in color.iter().enumerate() {
+            let target = if channel == 3 { 1.0 } else { *value };
+            let current = f32::from(self.pixels[i + channel]) / 255.0;
+            let blended = target * alpha + current * (1.0 - alpha);
+            self.pixels[i + channel] = (blended * 255.0).round() as u8;
+        }
+    }
+
+    /// Number of pixels with a channel differing by more than `tolerance` from `other`. Every
+    /// pixel differs from a frame of another size.
+    pub fn diff(&self, other: &Frame, tolerance: u8) -> usize {
+        if (self.width, self.height) != (other.width, other.height) {
+            return (self.width * self.height) as usize;
+        }
+        self.pixels
+            .chunks(4)
+            .zip(other.pixels.chunks(4))
+            .filter(|(a, b)| {
+                a.iter()
+                    .zip(b.iter())
+                    .any(|(a, b)| (i16::from(*a) - i16::from(*b)).abs() > i16::from(tolerance))
+            })
+            .count()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Frame::read_png(BufReader::new(File::open(path)?))
+    }
+
+    pub fn write_png<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+
+    /// Reads a PNG written by `write_png`. Other color types are rejected.
+    pub fn read_png<R: Read>(reader: R) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let (info, mut reader) = png::Decoder::new(reader)
+            .read_info()
+            .map_err(|e| invalid(e.to_string()))?;
+        if (info.color_type, info.bit_depth) != (png::ColorType::RGBA, png::BitDepth::Eight) {
+            return Err(invalid(format!(
+                "Expected an 8 bits RGBA image, got {:?} {:?}",
+                info.color_type, info.bit_depth
+            )));
+        }
+        let mut frame = Frame::new(info.width, info.height);
+        reader
+            .next_frame(&mut frame.pixels)
+            .map_err(|e| invalid(e.to_string()))?;
+        Ok(frame)
+    }
+}
+
+pub fn load_font<P: AsRef<Path>>(path: P) -> io::Result<Font<'static>> {
+    FontCollection::from_bytes(fs::read(path)?)
+        .into_font()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid font"))
+}
+
+/// Draws scenes into `frame`, without anti-aliasing except for the text.
+pub struct RasterRenderer {
+    pub frame: Frame,
+    font: Font<'static>,
+}
+
+impl RasterRenderer {
+    pub fn new(width: u32, height: u32, font: Font<'static>) -> Self {
+        RasterRenderer {
+            frame: Frame::new(width, height),
+            font,
+        }
+    }
+
+    pub fn rasterize(&mut self, commands: &[DrawCommand]) {
+        for command in commands {
+            match command {
+                DrawCommand::Clear(color) => {
+                    let (width, height) = (self.frame.width, self.frame.height);
+                    self.fill(*color, [0.0, 0.0, f64::from(width), f64::from(height)]);
+                }
+                DrawCommand::Rectangle { color, rect } => self.fill(*color, *rect),
+                DrawCommand::Circle {
+                    color,
+                    center,
+                    radius,
+                } => {
+                    let [cx, cy] = *center;
+                    let (x_range, y_range) = (
+                        pixel_range(cx - radius, cx + radius),
+                        pixel_range(cy - radius, cy + radius),
+                    );
+                    for y in y_range {
+                        for x in x_range.clone() {
+                            let (dx, dy) = (x as f64 + 0.5 - cx, y as f64 + 0.5 - cy);
+                            if dx * dx + dy * dy <= radius * radius {
+                                self.frame.blend(x, y, *color, 1.0);
+                            }
+                        }
+                    }
+                }
+                DrawCommand::Text {
+                    color,
+                    size,
+                    position,
+                    text,
+                } => {
+                    let start = point(position[0] as f32, position[1] as f32);
+                    let glyphs: Vec<_> = self
+                        .font
+                        .layout(text, Scale::uniform(*size as f32), start)
+                        .collect();
+                    for glyph in glyphs {
+                        if let Some(bounds) = glyph.pixel_bounding_box() {
+                            let frame = &mut self.frame;
+                            glyph.draw(|x, y, coverage| {
+                                let x = i64::from(bounds.min.x) + i64::from(x);
+                                let y = i64::from(bounds.min.y) + i64::from(y);
+                                frame.blend(x, y, *color, coverage);
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Paints the pixels whose center is in `rect`.
+    fn fill(&mut self, color: Color, rect: [f64; 4]) {
+        let [x, y, width, height] = rect;
+        for py in pixel_range(y, y + height) {
+            for px in pixel_range(x, x + width) {
+                self.frame.blend(px, py, color, 1.0);
+            }
+        }
+    }
+}
+
+impl Renderer for RasterRenderer {
+    fn draw(&mut self, _viewport: Viewport, commands: &[DrawCommand]) {
+        self.rasterize(commands);
+    }
+}
+
+/// Pixels whose center lies between `start` and `end`.
+fn pixel_range(start: f64, end: f64) -> std::ops::Range<i64> {
+    (start - 0.5).ceil() as i64..(end - 0.5).ceil() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::render::{BLACK, OVERLAY_COLOR, WHITE};
+
+    fn renderer() -> RasterRenderer {
+        RasterRenderer::new(4, 4, load_font("assets/arial.ttf").unwrap())
+    }
+
+    #[test]
+    fn it_should_fill_pixels_whose_center_is_covered() {
+        let mut raster = renderer();
+        raster.rasterize(&[
+            DrawCommand::Clear(BLACK),
+            DrawCommand::Rectangle {
+                color: WHITE,
+                rect: [0.6, 0.0, 2.0, 1.0],
+            },
+        ]);
+        assert_eq!([0, 0, 0, 255], raster.frame.pixel(0, 0));
+        assert_eq!([255, 255, 255, 255], raster.frame.pixel(1, 0));
+        assert_eq!([255, 255, 255, 255], raster.frame.pixel(2, 0));
+        assert_eq!([0, 0, 0, 255], raster.frame.pixel(1, 1));
+    }
+
+    #[test]
+    fn it_should_blend_translucent_colors() {
+        let mut raster = renderer();
+        raster.rasterize(&[
+            DrawCommand::Clear(WHITE),
+            DrawCommand::Rectangle {
+                color: OVERLAY_COLOR,
+                rect: [0.0, 0.0, 4.0, 4.0],
+            },
+        ]);
+        assert_eq!([102, 102, 102, 255], raster.frame.pixel(3, 3));
+    }
+
+    #[test]
+    fn it_should_read_what_it_wrote() {
+        let mut raster = renderer();
+        raster.rasterize(&[DrawCommand::Circle {
+            color: WHITE,
+            center: [2.0, 2.0],
+            radius: 1.0,
+        }]);
+        let mut buffer = Vec::new();
+        raster.frame.write_png(&mut buffer).unwrap();
+        let frame = Frame::read_png(&buffer[..]).unwrap();
+        assert_eq!(raster.frame, frame);
+        assert_eq!(0, frame.diff(&raster.frame, 0));
+    }
+}
+
+#[cfg(test)]
+mod golden_tests {
+    use super::*;
+    use crate::game::builder::{Controller, GameBuilder};
+    use crate::game::components::{Difficulty, Side};
+    use crate::game::{Game, State};
+    use rusttype::GlyphId;
+
+    /// Pixels allowed to differ from a reference, to absorb small changes in text rendering.
+    const MAX_DIFFERENT_PIXELS: usize = 50;
+
+    /// Compares the game's current frame with `tests/golden/<name>.png`, or writes it there
+    /// when `UPDATE_GOLDEN` is set.
+    fn assert_matches_golden(game: &Game, name: &str) {
+        let font = load_font("assets/arial.ttf").unwrap();
+        let scene = game.scene();
+        // a missing glyph would be drawn as a box and recorded as expected
+        for command in &scene {
+            if let DrawCommand::Text { text, .. } = command {
+                for c in text.chars().filter(|c| !c.is_whitespace()) {
+                    let glyph = font.glyph(c).unwrap();
+                    assert_ne!(GlyphId(0), glyph.id(), "No glyph for '{}' in '{}'", c, text);
+                }
+            }
+        }
+
+        let mut raster = RasterRenderer::new(250, 300, font);
+        raster.rasterize(&scene);
+        let path = format!("tests/golden/{}.png", name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            raster.frame.save(&path).unwrap();
+            return;
+        }
+        let reference =
+            Frame::load(&path).unwrap_or_else(|e| panic!("Failed to load '{}': {}", path, e));
+        let different = raster.frame.diff(&reference, 8);
+        assert!(
+            different <= MAX_DIFFERENT_PIXELS,
+            "{} pixels differ from {}, run with UPDATE_GOLDEN=1 to accept the change",
+            different,
+            path
+        );
+    }
+
+    fn ai_game() -> Game<'static, 'static> {
+        GameBuilder::new()
+            .seed(7)
+            .top_player(Controller::Ai(Difficulty::Hard))
+            .bottom_player(Controller::Ai(Difficulty::Normal))
+            .build()
+    }
+
+    #[test]
+    fn it_should_draw_the_menu() {
+        let game = GameBuilder::new().seed(7).menu().build();
+        assert_matches_golden(&game, "menu");
+    }
+
+    #[test]
+    fn it_should_draw_the_countdown() {
+        let game = ai_game();
+        assert_matches_golden(&game, "serving");
+    }
+
+    #[test]
+    fn it_should_draw_a_rally() {
+        let mut game = ai_game();
+        while game.tick() < 1500 {
+            game.step();
+        }
+        assert_eq!(State::Running, game.state());
+        assert_matches_golden(&game, "running");
+    }
+
+    #[test]
+    fn it_should_draw_four_players_with_lives() {
+        let game = GameBuilder::new()
+            .seed(7)
+            .player(Side::Left, Controller::Keyboard)
+            .player(Side::Right, Controller::Keyboard)
+            .lives(3)
+            .build();
+        assert_matches_golden(&game, "four_players");
+    }
+}
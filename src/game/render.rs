@@ -0,0 +1,296 @@
+//! Description of what is on screen, independent of the backend drawing it.
+
+use super::components::*;
+use super::input::{Action, InputConfig, Rebinding};
+use super::resources::*;
+use super::state::{State, StateMachine};
+use specs::prelude::*;
+
+/// Red, green, blue and alpha, between 0 and 1.
+pub type Color = [f32; 4];
+
+pub const BLACK: Color = [0.0, 0.0, 0.0, 1.0];
+pub const WHITE: Color = [1.0, 1.0, 1.0, 1.0];
+pub const TEXT_COLOR: Color = [0.7, 0.7, 0.7, 1.0];
+pub const OVERLAY_COLOR: Color = [0.0, 0.0, 0.0, 0.6];
+pub const TEXT_SIZE: u32 = 18;
+
+/// Something to draw, in pixels from the top left corner of the window.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DrawCommand {
+    /// Fills the whole window.
+    Clear(Color),
+    /// `rect` is the top left corner followed by the width and height.
+    Rectangle { color: Color, rect: [f64; 4] },
+    Circle {
+        color: Color,
+        center: [f64; 2],
+        radius: f64,
+    },
+    /// `position` is the left end of the text's baseline.
+    Text {
+        color: Color,
+        size: u32,
+        position: [f64; 2],
+        text: String,
+    },
+}
+
+/// Size of the surface drawn on, in pixels of the window and in pixels actually drawn, which
+/// are more numerous on high DPI screens.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Viewport {
+    pub width: u32,
+    pub height: u32,
+    pub draw_width: u32,
+    pub draw_height: u32,
+}
+
+impl Viewport {
+    /// A surface drawn one pixel of the window at a time.
+    pub fn new(width: u32, height: u32) -> Self {
+        Viewport {
+            width,
+            height,
+            draw_width: width,
+            draw_height: height,
+        }
+    }
+}
+
+/// Commands drawing the current frame, in order.
+#[derive(Default)]
+pub struct Scene(pub Vec<DrawCommand>);
+
+/// Something able to draw a scene.
+pub trait Renderer {
+    fn draw(&mut self, viewport: Viewport, commands: &[DrawCommand]);
+}
+
+/// Describes the arena, its entities, the counters and the messages of the current state.
+pub struct BuildScene;
+
+impl<'a> System<'a> for BuildScene {
+    type SystemData = (
+        ReadExpect<'a, GameArea>,
+        Read<'a, StateMachine>,
+        Read<'a, MatchRules>,
+        Read<'a, MatchStatus>,
+        Read<'a, InputConfig>,
+        Read<'a, Rebinding>,
+        Write<'a, Scene>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Shape>,
+        ReadStorage<'a, Score>,
+        ReadStorage<'a, Lives>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            area,
+            machine,
+            rules,
+            status,
+            config,
+            rebinding,
+            mut scene,
+            positions,
+            shapes,
+            scores,
+            lives,
+        ): Self::SystemData,
+    ) {
+        let commands = &mut scene.0;
+        commands.clear();
+        commands.push(DrawCommand::Clear(WHITE));
+        commands.push(DrawCommand::Rectangle {
+            color: BLACK,
+            rect: [0.0, 0.0, area.width, area.height],
+        });
+
+        for (position, shape) in (&positions, &shapes).join() {
+            let x = position.current.x * area.width;
+            let y = (1.0 - position.current.y) * area.height;
+            commands.push(match shape {
+                Shape::Circle { radius } => DrawCommand::Circle {
+                    color: WHITE,
+                    center: [x, y],
+                    radius: radius * area.width,
+                },
+                Shape::Rectangle { width, height } => DrawCommand::Rectangle {
+                    color: WHITE,
+                    rect: [
+                        x - width * 0.5 * area.width,
+                        y - height * 0.5 * area.height,
+                        width * area.width,
+                        height * area.height,
+                    ],
+                },
+            });
+        }
+
+        let counters = (&scores)
+            .join()
+            .map(|score| match rules.best_of {
+                1 => (score.position, format!("{}", score.current)),
+                _ => (
+                    score.position,
+                    format!("{} ({})", score.current, score.sets),
+                ),
+            })
+            .chain(
+                (&lives)
+                    .join()
+                    .map(|lives| (lives.position, format!("{} lives", lives.current))),
+            );
+        for (position, counter) in counters {
+            commands.push(DrawCommand::Text {
+                color: BLACK,
+                size: TEXT_SIZE,
+                position: [
+                    position.x * area.width,
+                    (1.0 - position.y) * area.height + TEXT_SIZE as f64 * 0.5,
+                ],
+                text: counter,
+            });
+        }
+
+        if machine.is(State::Paused) {
+            commands.push(DrawCommand::Rectangle {
+                color: OVERLAY_COLOR,
+                rect: [0.0, 0.0, area.width, area.height],
+            });
+        }
+
+        for (i, message) in messages(&machine, &status, &config, &rebinding)
+            .into_iter()
+            .enumerate()
+        {
+            commands.push(DrawCommand::Text {
+                color: TEXT_COLOR,
+                size: TEXT_SIZE,
+                position: [
+                    area.width * 0.5 - 60.0,
+                    area.height * 0.5 + TEXT_SIZE as f64 * (0.5 + 1.5 * i as f64),
+                ],
+                text: message,
+            });
+        }
+    }
+}
+
+/// Lines of text shown over the arena in the current state.
+fn messages(
+    machine: &StateMachine,
+    status: &MatchStatus,
+    config: &InputConfig,
+    rebinding: &Rebinding,
+) -> Vec<String> {
+    let key = |action| {
+        config
+            .game
+            .get(action)
+            .first()
+            .map_or_else(|| "?".to_string(), |binding| binding.to_string())
+    };
+    match machine.current() {
+        State::Menu => vec![
+            "Pong".to_string(),
+            format!("{} to play", key(Action::Serve)),
+            format!("{} for keys", key(Action::Rebind)),
+        ],
+        State::Serving => vec![
+            status
+                .server
+                .map_or_else(String::new, |s| format!("{:?} serves", s)),
+            status.countdown.map_or_else(
+                || format!("{} to serve", key(Action::Serve)),
+                |countdown| format!("{}", countdown.ceil()),
+            ),
+        ],
+        State::Running => vec![],
+        State::Paused => vec![
+            "Paused".to_string(),
+            format!("{} to resume", key(Action::Pause)),
+            format!("{} to quit", key(Action::Back)),
+        ],
+        State::GameOver => vec![
+            status
+                .winner
+                .map_or_else(|| "Game over".to_string(), |w| format!("{:?} wins", w)),
+            format!("{} for a rematch", key(Action::Serve)),
+        ],
+        State::Rebinding => {
+            let mut lines = vec!["Bindings".to_string()];
+            if let Some((owner, action)) = rebinding.next_action() {
+                lines.push(match owner {
+                    Some(side) => format!("{:?} {:?}", side, action),
+                    None => format!("{:?}", action),
+                });
+                lines.push("Press a key".to_string());
+            }
+            if let Some(conflict) = config.conflicts().first() {
+                lines.push(format!("{} used twice", conflict.binding));
+            }
+            lines.push(format!("{} to finish", key(Action::Back)));
+            lines
+        }
+    }
+}
+
+/// Hands the scene over to a renderer each time the game is asked to render.
+pub struct Render<R>(pub R);
+
+impl<'a, R: Renderer> System<'a> for Render<R> {
+    type SystemData = (Write<'a, RenderEvent>, Read<'a, Scene>);
+
+    fn run(&mut self, (mut event, scene): Self::SystemData) {
+        if let Some(viewport) = event.0.take() {
+            self.0.draw(viewport, &scene.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameBuilder;
+
+    #[test]
+    fn it_should_scale_entities_to_the_arena() {
+        let game = GameBuilder::new().seed(1).arena(200.0, 300.0).build();
+        let commands = game.scene();
+
+        assert_eq!(
+            Some(&DrawCommand::Circle {
+                color: WHITE,
+                center: [100.0, 150.0],
+                radius: 5.0,
+            }),
+            commands
+                .iter()
+                .find(|command| matches!(command, DrawCommand::Circle { .. }))
+        );
+        // the bottom paddle, a quarter of the arena wide
+        assert!(commands.contains(&DrawCommand::Rectangle {
+            color: WHITE,
+            rect: [75.0, 285.0, 50.0, 15.0],
+        }));
+    }
+
+    #[test]
+    fn it_should_show_the_messages_of_the_state() {
+        let game = GameBuilder::new().menu().build();
+        let texts: Vec<String> = game
+            .scene()
+            .into_iter()
+            .filter_map(|command| match command {
+                DrawCommand::Text { text, .. } => Some(text),
+                _ => None,
+            })
+            .collect();
+        assert!(texts.contains(&"Pong".to_string()));
+        assert!(texts.contains(&"Space to play".to_string()));
+    }
+}
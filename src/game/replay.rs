@@ -0,0 +1,199 @@
+use super::builder::Player;
+use super::input::{InputConfig, InputKind};
+use super::resources::{BallSettings, BounceSettings, InputEvent, MatchRules, PaddleSettings};
+use serde_derive::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Version written in new replay files. Files with another version are rejected.
+pub const REPLAY_VERSION: u32 = 1;
+
+pub const MIN_PLAYBACK_SPEED: f64 = 0.25;
+pub const MAX_PLAYBACK_SPEED: f64 = 4.0;
+
+/// Everything needed to recreate a game before replaying its inputs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplaySettings {
+    pub tick_rate: u32,
+    pub players: Vec<Player>,
+    pub lives: Option<u32>,
+    pub rules: MatchRules,
+    pub ball: BallSettings,
+    pub paddle: PaddleSettings,
+    pub bounce: BounceSettings,
+    pub bindings: InputConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub settings: ReplaySettings,
+    pub inputs: Vec<InputEvent>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, settings: ReplaySettings) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            seed,
+            settings,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Appends an input. Inputs must be recorded in tick order.
+    pub fn record(&mut self, tick: u64, kind: InputKind) {
+        self.inputs.push(InputEvent { tick, kind });
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Replay::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: R) -> io::Result<Self> {
+        let replay: Replay = serde_json::from_reader(reader)?;
+        if replay.version != REPLAY_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Unsupported replay version {} (expected {})",
+                    replay.version, REPLAY_VERSION
+                ),
+            ));
+        }
+        Ok(replay)
+    }
+}
+
+/// Feeds the inputs of a replay back tick after tick.
+pub struct Playback {
+    replay: Replay,
+    cursor: usize,
+    speed: f64,
+    pub paused: bool,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Playback {
+            replay,
+            cursor: 0,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = num::clamp(speed, MIN_PLAYBACK_SPEED, MAX_PLAYBACK_SPEED);
+    }
+
+    /// Goes back to the first input, to be used when the game is recreated from scratch.
+    pub fn rewind(&mut self) {
+        self.cursor = 0;
+    }
+
+    /// Returns the inputs to apply before running `tick`, skipping any older one.
+    pub fn inputs_for(&mut self, tick: u64) -> &[InputEvent] {
+        let inputs = &self.replay.inputs;
+        while self.cursor < inputs.len() && inputs[self.cursor].tick < tick {
+            self.cursor += 1;
+        }
+        let start = self.cursor;
+        while self.cursor < inputs.len() && inputs[self.cursor].tick == tick {
+            self.cursor += 1;
+        }
+        &inputs[start..self.cursor]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::builder::Controller;
+    use crate::game::components::{Difficulty, Side};
+    use piston::input::{Button, Key};
+
+    fn replay() -> Replay {
+        let mut replay = Replay::new(
+            42,
+            ReplaySettings {
+                tick_rate: 120,
+                players: vec![
+                    Player {
+                        side: Side::Top,
+                        controller: Controller::Ai(Difficulty::Hard),
+                    },
+                    Player {
+                        side: Side::Bottom,
+                        controller: Controller::Keyboard,
+                    },
+                ],
+                lives: None,
+                rules: MatchRules::default(),
+                ball: BallSettings::default(),
+                paddle: PaddleSettings::default(),
+                bounce: BounceSettings::default(),
+                bindings: InputConfig::default(),
+            },
+        );
+        replay.record(3, InputKind::Press(Button::Keyboard(Key::Left)));
+        replay.record(3, InputKind::Press(Button::Keyboard(Key::Right)));
+        replay.record(10, InputKind::Release(Button::Keyboard(Key::Left)));
+        replay
+    }
+
+    #[test]
+    fn it_should_read_what_it_wrote() {
+        let mut buffer = Vec::new();
+        replay().write_to(&mut buffer).unwrap();
+        assert_eq!(replay(), Replay::read_from(&buffer[..]).unwrap());
+    }
+
+    #[test]
+    fn it_should_reject_other_versions() {
+        let mut old = replay();
+        old.version = REPLAY_VERSION + 1;
+        let mut buffer = Vec::new();
+        old.write_to(&mut buffer).unwrap();
+        assert!(Replay::read_from(&buffer[..]).is_err());
+    }
+
+    #[test]
+    fn it_should_return_inputs_of_each_tick() {
+        let mut playback = Playback::new(replay());
+        assert!(playback.inputs_for(0).is_empty());
+        assert_eq!(2, playback.inputs_for(3).len());
+        assert!(playback.inputs_for(4).is_empty());
+        assert_eq!(1, playback.inputs_for(10).len());
+        playback.rewind();
+        assert_eq!(2, playback.inputs_for(3).len());
+    }
+
+    #[test]
+    fn it_should_clamp_speed() {
+        let mut playback = Playback::new(replay());
+        playback.set_speed(10.0);
+        assert_eq!(MAX_PLAYBACK_SPEED, playback.speed());
+        playback.set_speed(0.1);
+        assert_eq!(MIN_PLAYBACK_SPEED, playback.speed());
+    }
+}
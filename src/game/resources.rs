@@ -1,8 +1,11 @@
-use piston::input::{RenderArgs, Button};
-use super::State;
-
-#[derive(Default)]
-pub struct GameState(pub State);
+use super::components::Side;
+use super::input::InputKind;
+use super::render::Viewport;
+use crate::math::Vector;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_derive::{Deserialize, Serialize};
+use std::str::FromStr;
 
 pub struct GameArea {
     pub width: f64,
@@ -12,11 +15,323 @@ pub struct GameArea {
 #[derive(Default)]
 pub struct DeltaTime(pub f64);
 
+/// Source of every random decision made by the game.
+///
+/// Two games created with the same seed and fed the same inputs play out identically.
+pub struct Random {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns a value in `[low, high)`.
+    pub fn gen_range(&mut self, low: f64, high: f64) -> f64 {
+        self.rng.gen_range(low, high)
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Random::new(rand::random())
+    }
+}
+
+/// Number of simulation ticks run since the game started.
+#[derive(Default)]
+pub struct Tick(pub u64);
+
+/// Number of times a ball bounced off a paddle since the game started.
+#[derive(Default)]
+pub struct PaddleHits(pub u64);
+
+/// Controls how a ball bounces off the face of a paddle.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BounceSettings {
+    /// Largest angle (in radians) between the outgoing ball and the paddle's normal.
+    /// It is reached when the ball hits the very edge of the paddle.
+    pub max_angle: f64,
+    /// How much the paddle's own movement bends the outgoing angle, from 0 (not at all)
+    /// to 1 (a paddle moving at full speed adds `max_angle`).
+    pub paddle_influence: f64,
+}
+
+impl Default for BounceSettings {
+    fn default() -> Self {
+        BounceSettings {
+            max_angle: std::f64::consts::PI / 3.0,
+            paddle_influence: 0.25,
+        }
+    }
+}
+
+/// Size and speed of the ball, in normalized coordinates.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BallSettings {
+    pub radius: f64,
+    /// Speed of the ball when it is served.
+    pub speed: f64,
+    pub max_speed: f64,
+    /// Factor applied to the speed of the ball each time it bounces off a paddle.
+    pub speed_up: f64,
+}
+
+impl Default for BallSettings {
+    fn default() -> Self {
+        BallSettings {
+            radius: 0.025,
+            speed: 0.5,
+            max_speed: 2.0,
+            speed_up: 1.1,
+        }
+    }
+}
+
+/// Size and speed of the paddles, in normalized coordinates.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaddleSettings {
+    /// Size along the side a paddle defends.
+    pub length: f64,
+    pub thickness: f64,
+    pub max_speed: f64,
+}
+
+impl Default for PaddleSettings {
+    fn default() -> Self {
+        PaddleSettings {
+            length: 0.25,
+            thickness: 0.05,
+            max_speed: 0.55,
+        }
+    }
+}
+
+/// How a match is won.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MatchRules {
+    /// Points needed to win a set.
+    pub points: u32,
+    /// Whether a set is only won with a lead of at least two points.
+    pub win_by_two: bool,
+    /// Most sets played. The first player winning more than half of them wins the match.
+    pub best_of: u32,
+    /// Seconds of play after which the leading player wins the set. When players are tied
+    /// at that time, the next point wins it.
+    pub time_limit: Option<f64>,
+    pub serve: ServeRule,
+    /// Whether a keyboard player releases the ball when serving instead of waiting for the
+    /// countdown.
+    pub manual_serve: bool,
+}
+
+impl MatchRules {
+    pub fn sets_to_win(&self) -> u32 {
+        self.best_of / 2 + 1
+    }
+
+    /// Whether the time limit of a set ran out after `set_time` seconds of play.
+    pub fn is_sudden_death(&self, set_time: f64) -> bool {
+        self.time_limit.is_some_and(|limit| set_time >= limit)
+    }
+
+    /// Returns the index of the player winning a set with `scores`, if any.
+    pub fn set_winner(&self, scores: &[u32], set_time: f64) -> Option<usize> {
+        let (leader, &best) = scores.iter().enumerate().max_by_key(|(_, score)| **score)?;
+        let second = scores
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != leader)
+            .map(|(_, score)| *score)
+            .max()
+            .unwrap_or(0);
+        let lead = best.saturating_sub(second);
+        let needed_lead = if self.win_by_two { 2 } else { 1 };
+        let won = (best >= self.points && lead >= needed_lead)
+            || (self.is_sudden_death(set_time) && lead > 0);
+        Some(leader).filter(|_| won)
+    }
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        MatchRules {
+            points: 11,
+            win_by_two: false,
+            best_of: 1,
+            time_limit: None,
+            serve: ServeRule::default(),
+            manual_serve: false,
+        }
+    }
+}
+
+/// Who serves after a point.
+#[derive(Debug, Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum ServeRule {
+    /// Players take turns.
+    #[default]
+    Alternate,
+    /// The player who lost the point.
+    LoserServes,
+    /// The player who won the point.
+    WinnerServes,
+}
+
+impl ServeRule {
+    /// Picks who serves among the players on `sides`, `server` having served the rally that
+    /// ended with `point`. Returns `None` when the server should be drawn at random.
+    pub fn next_server(
+        &self,
+        sides: &[Side],
+        server: Option<Side>,
+        point: Option<Point>,
+    ) -> Option<Side> {
+        let point = match point {
+            Some(point) => point,
+            None => return server.filter(|side| sides.contains(side)),
+        };
+        let by_rule = match self {
+            ServeRule::Alternate => None,
+            ServeRule::LoserServes => point.loser,
+            ServeRule::WinnerServes => point.winner,
+        };
+        by_rule.filter(|side| sides.contains(side)).or_else(|| {
+            let index = sides.iter().position(|side| Some(*side) == server)?;
+            Some(sides[(index + 1) % sides.len()])
+        })
+    }
+}
+
+impl FromStr for ServeRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alternate" => Ok(ServeRule::Alternate),
+            "loser" => Ok(ServeRule::LoserServes),
+            "winner" => Ok(ServeRule::WinnerServes),
+            _ => Err(format!("Unknown serve rule '{}'", s)),
+        }
+    }
+}
+
+/// Players who won and lost a point, when they are known.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Point {
+    pub winner: Option<Side>,
+    pub loser: Option<Side>,
+}
+
+/// Progress of the match being played.
 #[derive(Default)]
-pub struct RenderEvent(pub Option<RenderArgs>);
+pub struct MatchStatus {
+    /// Seconds the ball was in play during the current set.
+    pub set_time: f64,
+    /// Side of the player who won the match, once it is over.
+    pub winner: Option<Side>,
+    /// Side of the player serving the current rally.
+    pub server: Option<Side>,
+    /// Last point played, until the next serve is prepared.
+    pub last_point: Option<Point>,
+    /// Seconds left before the ball is served, or `None` when the server releases it.
+    pub countdown: Option<f64>,
+}
 
 #[derive(Default)]
-pub struct PressEvent(pub Option<Button>);
+pub struct RenderEvent(pub Option<Viewport>);
+
+/// An input received just before the simulation ran `tick`.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputEvent {
+    pub tick: u64,
+    pub kind: InputKind,
+}
 
+/// Button events received since the last time they were handled, oldest first.
 #[derive(Default)]
-pub struct ReleaseEvent(pub Option<Button>);
+pub struct InputEvents(pub Vec<InputEvent>);
+
+/// Last known position of the mouse cursor, in normalized coordinates.
+#[derive(Default)]
+pub struct Cursor(pub Option<Vector>);
+
+#[cfg(test)]
+mod match_rules_tests {
+    use super::*;
+
+    fn rules(points: u32, win_by_two: bool) -> MatchRules {
+        MatchRules {
+            points,
+            win_by_two,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_should_give_the_set_to_the_first_player_reaching_the_points() {
+        assert_eq!(None, rules(5, false).set_winner(&[4, 3], 0.0));
+        assert_eq!(Some(1), rules(5, false).set_winner(&[4, 5], 0.0));
+    }
+
+    #[test]
+    fn it_should_require_a_two_points_lead() {
+        assert_eq!(None, rules(5, true).set_winner(&[5, 4], 0.0));
+        assert_eq!(None, rules(5, true).set_winner(&[6, 6], 0.0));
+        assert_eq!(Some(0), rules(5, true).set_winner(&[7, 5], 0.0));
+    }
+
+    #[test]
+    fn it_should_give_the_set_to_the_leader_when_time_runs_out() {
+        let rules = MatchRules {
+            time_limit: Some(60.0),
+            ..rules(5, true)
+        };
+        assert_eq!(None, rules.set_winner(&[2, 1], 59.0));
+        assert_eq!(Some(0), rules.set_winner(&[2, 1], 60.0));
+        assert_eq!(None, rules.set_winner(&[2, 2], 60.0));
+    }
+
+    #[test]
+    fn it_should_pick_the_server_following_the_rule() {
+        let sides = [Side::Top, Side::Bottom];
+        let point = Some(Point {
+            winner: Some(Side::Bottom),
+            loser: Some(Side::Top),
+        });
+        let top = Some(Side::Top);
+        assert_eq!(top, ServeRule::Alternate.next_server(&sides, top, None));
+        assert_eq!(
+            Some(Side::Bottom),
+            ServeRule::Alternate.next_server(&sides, top, point)
+        );
+        assert_eq!(top, ServeRule::LoserServes.next_server(&sides, top, point));
+        assert_eq!(
+            Some(Side::Bottom),
+            ServeRule::WinnerServes.next_server(&sides, top, point)
+        );
+        assert_eq!(None, ServeRule::Alternate.next_server(&sides, None, point));
+    }
+
+    #[test]
+    fn it_should_need_a_majority_of_sets() {
+        let rules = MatchRules {
+            best_of: 5,
+            ..Default::default()
+        };
+        assert_eq!(3, rules.sets_to_win());
+    }
+}
@@ -0,0 +1,194 @@
+use serde_derive::Serialize;
+use specs::prelude::*;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default, Serialize)]
+pub enum State {
+    /// Main menu shown before the first serve.
+    #[default]
+    Menu,
+    /// The ball waits at the center to be served.
+    Serving,
+    Running,
+    /// The simulation is frozen until the game is resumed.
+    Paused,
+    /// The match is won, waiting for a rematch.
+    GameOver,
+    /// Bindings screen, opened from the main menu.
+    Rebinding,
+}
+
+/// Current state of the game.
+///
+/// Systems only ask for a transition, it happens between two ticks so that every system of a
+/// tick sees the same state. Like `LazyUpdate`, requests only need to read the resource.
+#[derive(Default)]
+pub struct StateMachine {
+    current: State,
+    previous: State,
+    next: Mutex<Option<State>>,
+}
+
+impl StateMachine {
+    pub fn new(state: State) -> Self {
+        StateMachine {
+            current: state,
+            previous: state,
+            next: Mutex::new(None),
+        }
+    }
+
+    pub fn current(&self) -> State {
+        self.current
+    }
+
+    /// State the game was in before the current one.
+    pub fn previous(&self) -> State {
+        self.previous
+    }
+
+    pub fn is(&self, state: State) -> bool {
+        self.current == state
+    }
+
+    /// Asks to move to `state` at the end of the tick. The last request wins.
+    pub fn request(&self, state: State) {
+        *self.next.lock().unwrap() = Some(state);
+    }
+
+    /// Moves to the requested state, returning the previous and new states if they differ.
+    pub fn take_transition(&mut self) -> Option<(State, State)> {
+        let next = self
+            .next
+            .get_mut()
+            .unwrap()
+            .take()
+            .filter(|next| *next != self.current)?;
+        self.previous = std::mem::replace(&mut self.current, next);
+        Some((self.previous, next))
+    }
+}
+
+pub type Hook = Box<dyn FnMut(&mut World)>;
+
+/// Functions called when the game enters or leaves a state.
+#[derive(Default)]
+pub struct StateHooks {
+    enter: HashMap<State, Vec<Hook>>,
+    exit: HashMap<State, Vec<Hook>>,
+}
+
+impl StateHooks {
+    pub fn on_enter(&mut self, state: State, hook: Hook) {
+        self.enter.entry(state).or_default().push(hook);
+    }
+
+    pub fn on_exit(&mut self, state: State, hook: Hook) {
+        self.exit.entry(state).or_default().push(hook);
+    }
+
+    /// Adds the hooks of `other` after those already registered.
+    pub fn extend(&mut self, other: StateHooks) {
+        for (state, hooks) in other.enter {
+            self.enter.entry(state).or_default().extend(hooks);
+        }
+        for (state, hooks) in other.exit {
+            self.exit.entry(state).or_default().extend(hooks);
+        }
+    }
+
+    /// Applies the transitions requested to the `StateMachine` of `world`, including those
+    /// requested by the hooks themselves.
+    pub fn apply(&mut self, world: &mut World) {
+        loop {
+            let transition = world.write_resource::<StateMachine>().take_transition();
+            let (previous, next) = match transition {
+                Some(transition) => transition,
+                None => return,
+            };
+            for hook in self.exit.get_mut(&previous).into_iter().flatten() {
+                hook(world);
+            }
+            for hook in self.enter.get_mut(&next).into_iter().flatten() {
+                hook(world);
+            }
+        }
+    }
+}
+
+/// Declares the states in which a system runs.
+pub trait RunsIn {
+    const STATES: &'static [State];
+}
+
+/// Runs the wrapped system only while the game is in one of the states it runs in.
+pub struct Gated<S>(pub S);
+
+impl<'a, S> System<'a> for Gated<S>
+where
+    S: System<'a> + RunsIn,
+    S::SystemData: SystemData<'a>,
+{
+    type SystemData = (Read<'a, StateMachine>, S::SystemData);
+
+    fn run(&mut self, (machine, data): Self::SystemData) {
+        if S::STATES.contains(&machine.current()) {
+            self.0.run(data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn it_should_only_move_when_asked_for_another_state() {
+        let mut machine = StateMachine::new(State::Running);
+        assert_eq!(None, machine.take_transition());
+        machine.request(State::Running);
+        assert_eq!(None, machine.take_transition());
+        machine.request(State::Serving);
+        machine.request(State::GameOver);
+        assert_eq!(
+            Some((State::Running, State::GameOver)),
+            machine.take_transition()
+        );
+        assert!(machine.is(State::GameOver));
+        assert_eq!(State::Running, machine.previous());
+    }
+
+    #[test]
+    fn it_should_call_exit_then_enter_hooks() {
+        let mut world = World::new();
+        world.add_resource(StateMachine::new(State::Menu));
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut hooks = StateHooks::default();
+        let exit_calls = calls.clone();
+        hooks.on_exit(
+            State::Menu,
+            Box::new(move |_| exit_calls.borrow_mut().push("exit menu")),
+        );
+        let enter_calls = calls.clone();
+        hooks.on_enter(
+            State::Serving,
+            Box::new(move |world| {
+                enter_calls.borrow_mut().push("enter serving");
+                world
+                    .read_resource::<StateMachine>()
+                    .request(State::Running);
+            }),
+        );
+
+        world
+            .read_resource::<StateMachine>()
+            .request(State::Serving);
+        hooks.apply(&mut world);
+
+        assert_eq!(vec!["exit menu", "enter serving"], *calls.borrow());
+        assert!(world.read_resource::<StateMachine>().is(State::Running));
+    }
+}
@@ -1,78 +1,116 @@
-use super::{components::*, resources::*, State};
+use super::input::{Action, Binding, InputConfig, InputKind};
+use super::state::{RunsIn, State, StateMachine};
+use super::{components::*, resources::*};
 use crate::math::*;
-use graphics::{clear, ellipse, rectangle, text, Transformed};
-use opengl_graphics::{GlGraphics, GlyphCache};
-use piston::input::Button;
-use rand::random;
 use specs::prelude::*;
 
-const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
-const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
-const TEXT_COLOR: [f32; 4] = [0.7, 0.7, 0.7, 1.0];
-const TEXT_SIZE: u32 = 18;
+/// Seconds between the end of a point and the next serve.
+pub const SERVE_COUNTDOWN: f64 = 3.0;
 
-pub const BALL_DEFAULT_SPEED: f64 = 0.5;
+/// Picks a random direction for a ball being served by the player on `server`, or towards
+/// any player when nobody serves.
+pub fn serve_direction(random: &mut Random, server: Option<Side>) -> Vector {
+    match server {
+        Some(side) => {
+            let across = side.axis() * random.gen_range(-1.0, 1.0);
+            (across - side.outward() * random.gen_range(1.0, 5.0)).normalize()
+        }
+        None => Vector::new(random.gen_range(-1.0, 1.0), random.gen_range(-5.0, 5.0)).normalize(),
+    }
+}
 
 pub struct InputUpdate;
 
+// inputs are handled in every state receiving them so that none is left over for the next
+// match, the keys held when the ball is served still counting
+impl RunsIn for InputUpdate {
+    const STATES: &'static [State] = &[
+        State::Menu,
+        State::Serving,
+        State::Running,
+        State::Paused,
+        State::GameOver,
+    ];
+}
+
 impl<'a> System<'a> for InputUpdate {
     type SystemData = (
-        Read<'a, GameState>,
-        Write<'a, PressEvent>,
-        Write<'a, ReleaseEvent>,
+        Read<'a, InputConfig>,
+        ReadExpect<'a, GameArea>,
+        Write<'a, InputEvents>,
+        Write<'a, Cursor>,
+        ReadStorage<'a, Paddle>,
         WriteStorage<'a, Input>,
     );
 
-    fn run(&mut self, (state, mut press_evt, mut release_evt, mut inputs): Self::SystemData) {
-        if let GameState(State::Idle) = *state {
-            return;
-        }
-
-        if let Some(Button::Keyboard(key)) = press_evt.0 {
-            for input in (&mut inputs).join() {
-                if key == input.key_right {
-                    input.right = true;
+    fn run(
+        &mut self,
+        (config, area, mut events, mut cursor, paddles, mut inputs): Self::SystemData,
+    ) {
+        let (mut dx, mut dy) = (0.0, 0.0);
+        for event in events.0.drain(..) {
+            match event.kind {
+                InputKind::Press(button) => {
+                    for input in (&mut inputs).join() {
+                        if !input.held.contains(&button) {
+                            input.held.push(button);
+                        }
+                    }
                 }
-                if key == input.key_left {
-                    input.left = true;
+                InputKind::Release(button) => {
+                    for input in (&mut inputs).join() {
+                        input.held.retain(|held| *held != button);
+                    }
                 }
-            }
-        }
-        *press_evt = PressEvent(None);
-
-        if let Some(Button::Keyboard(key)) = release_evt.0 {
-            for input in (&mut inputs).join() {
-                if key == input.key_right {
-                    input.right = false;
+                InputKind::MouseMotion(x, y) => {
+                    dx += x;
+                    dy += y;
                 }
-                if key == input.key_left {
-                    input.left = false;
+                // the window's y axis points down
+                InputKind::MouseCursor(x, y) => {
+                    cursor.0 = Some(Vector::new(x / area.width, 1.0 - y / area.height));
                 }
             }
         }
-        *release_evt = ReleaseEvent(None);
+
+        for (paddle, input) in (&paddles, &mut inputs).join() {
+            let bindings = match config.players.get(&paddle.side) {
+                Some(bindings) => bindings,
+                None => continue,
+            };
+            let active = |action| {
+                bindings.get(action).iter().any(|binding| match binding {
+                    Binding::Button(button) => input.held.contains(button),
+                    Binding::Mouse { .. } => binding.matches_motion(dx, dy),
+                })
+            };
+            let (left, right) = (active(Action::MoveLeft), active(Action::MoveRight));
+            input.left = left;
+            input.right = right;
+        }
     }
 }
 
 pub struct InputApply;
 
+impl RunsIn for InputApply {
+    const STATES: &'static [State] = &[State::Running];
+}
+
 impl<'a> System<'a> for InputApply {
     type SystemData = (
-        Read<'a, GameState>,
         ReadStorage<'a, Input>,
+        ReadStorage<'a, Paddle>,
         WriteStorage<'a, Velocity>,
     );
 
-    fn run(&mut self, (state, inputs, mut velocities): Self::SystemData) {
-        if let GameState(State::Idle) = *state {
-            return;
-        }
-
-        for (input, velocity) in (&inputs, &mut velocities).join() {
+    fn run(&mut self, (inputs, paddles, mut velocities): Self::SystemData) {
+        for (input, paddle, velocity) in (&inputs, &paddles, &mut velocities).join() {
+            // right moves up on vertical paddles
             if input.right {
-                velocity.direction = Vector::new(1.0, 0.0);
+                velocity.direction = paddle.side.axis();
             } else if input.left {
-                velocity.direction = Vector::new(-1.0, 0.0);
+                velocity.direction = -paddle.side.axis();
             } else {
                 velocity.direction = Default::default();
             }
@@ -80,23 +118,174 @@ impl<'a> System<'a> for InputApply {
     }
 }
 
+/// Moves the paddles of the agents as commanded.
+pub struct AgentControl;
+
+impl RunsIn for AgentControl {
+    const STATES: &'static [State] = &[State::Running];
+}
+
+impl<'a> System<'a> for AgentControl {
+    type SystemData = (
+        ReadStorage<'a, Agent>,
+        ReadStorage<'a, Paddle>,
+        WriteStorage<'a, Velocity>,
+    );
+
+    fn run(&mut self, (agents, paddles, mut velocities): Self::SystemData) {
+        for (agent, paddle, velocity) in (&agents, &paddles, &mut velocities).join() {
+            velocity.direction = match agent.command {
+                Move::Left => -paddle.side.axis(),
+                Move::Stay => Default::default(),
+                Move::Right => paddle.side.axis(),
+            };
+        }
+    }
+}
+
+/// Moves the paddles following the cursor towards it, as fast as their max speed allows.
+pub struct CursorControl;
+
+impl RunsIn for CursorControl {
+    const STATES: &'static [State] = &[State::Running];
+}
+
+impl<'a> System<'a> for CursorControl {
+    type SystemData = (
+        Read<'a, DeltaTime>,
+        Read<'a, Cursor>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Paddle>,
+        ReadStorage<'a, FollowCursor>,
+        WriteStorage<'a, Velocity>,
+    );
+
+    fn run(
+        &mut self,
+        (delta, cursor, positions, paddles, followers, mut velocities): Self::SystemData,
+    ) {
+        for (position, paddle, _, velocity) in
+            (&positions, &paddles, &followers, &mut velocities).join()
+        {
+            let axis = paddle.side.axis();
+            let gap = cursor
+                .0
+                .map_or(0.0, |cursor| (cursor - position.current).dot(axis));
+            // slow down when getting close so that the paddle stops on the cursor
+            velocity.speed = (gap.abs() / delta.0).min(velocity.max_speed);
+            velocity.direction = if gap == 0.0 {
+                Default::default()
+            } else {
+                axis * gap.signum()
+            };
+        }
+    }
+}
+
+pub struct AiControl;
+
+impl RunsIn for AiControl {
+    const STATES: &'static [State] = &[State::Running];
+}
+
+impl<'a> System<'a> for AiControl {
+    type SystemData = (
+        Read<'a, DeltaTime>,
+        Write<'a, Random>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Shape>,
+        ReadStorage<'a, Ball>,
+        ReadStorage<'a, Paddle>,
+        WriteStorage<'a, Ai>,
+        WriteStorage<'a, Velocity>,
+    );
+
+    fn run(
+        &mut self,
+        (delta, mut random, positions, shapes, balls, paddles, mut ais, mut velocities): Self::SystemData,
+    ) {
+        let ball = (&positions, &velocities, &shapes, &balls)
+            .join()
+            .filter_map(|(position, velocity, shape, _)| match shape {
+                Shape::Circle { radius } => Some((position.current, velocity.direction, *radius)),
+                _ => None,
+            })
+            .next();
+
+        for (ai, position, shape, paddle, velocity) in
+            (&mut ais, &positions, &shapes, &paddles, &mut velocities).join()
+        {
+            let axis = paddle.side.axis();
+            let outward = paddle.side.outward();
+
+            ai.cooldown -= delta.0;
+            if ai.cooldown <= 0.0 {
+                ai.cooldown = ai.difficulty.reaction_delay();
+                ai.target = Some(0.5);
+                if let (Some((ball_pos, ball_dir, radius)), Shape::Rectangle { width, height }) =
+                    (ball, shape)
+                {
+                    if ball_dir.dot(outward) > 0.0 {
+                        let thickness = Vector::new(*width, *height).dot(outward).abs();
+                        let line = position.current - outward * (thickness * 0.5 + radius);
+                        let intercept = predict_intercept(ball_pos, ball_dir, line, axis, radius);
+                        let error = ai.difficulty.prediction_error();
+                        ai.target = Some(intercept + random.gen_range(-error, error));
+                    }
+                }
+            }
+
+            velocity.speed = velocity.max_speed * ai.difficulty.speed_factor();
+            velocity.direction = Default::default();
+            if let Some(target) = ai.target {
+                let gap = target - position.current.dot(axis);
+                if gap.abs() > velocity.speed * delta.0 {
+                    velocity.direction = axis * gap.signum();
+                }
+            }
+        }
+    }
+}
+
+/// Returns the coordinate along `axis` at which a ball will cross the line parallel
+/// to `axis` going through `line`, accounting for its bounces off the walls.
+fn predict_intercept(
+    position: Vector,
+    direction: Vector,
+    line: Vector,
+    axis: Vector,
+    radius: f64,
+) -> f64 {
+    let across = Vector::new(axis.y, axis.x);
+    let speed_across = direction.dot(across);
+    if speed_across == 0.0 {
+        return position.dot(axis);
+    }
+    let travel = (line - position).dot(across) / speed_across;
+    bounce_within(
+        (position + direction * travel).dot(axis),
+        radius,
+        1.0 - radius,
+    )
+}
+
 pub struct Movement;
 
+impl RunsIn for Movement {
+    const STATES: &'static [State] = &[State::Running];
+}
+
 impl<'a> System<'a> for Movement {
     type SystemData = (
-        Read<'a, GameState>,
         Read<'a, DeltaTime>,
         WriteStorage<'a, Position>,
         ReadStorage<'a, Velocity>,
     );
 
-    fn run(&mut self, (state, delta, mut positions, velocities): Self::SystemData) {
-        if let GameState(State::Idle) = *state {
-            return;
-        }
-
+    fn run(&mut self, (delta, mut positions, velocities): Self::SystemData) {
         let delta = delta.0;
         for (position, velocity) in (&mut positions, &velocities).join() {
+            position.previous = position.current;
             position.current += velocity.direction * velocity.speed * delta;
         }
     }
@@ -104,43 +293,54 @@ impl<'a> System<'a> for Movement {
 
 pub struct OutOfBound;
 
+impl RunsIn for OutOfBound {
+    const STATES: &'static [State] = &[State::Running];
+}
+
 impl<'a> System<'a> for OutOfBound {
     type SystemData = (
-        Read<'a, GameState>,
         WriteStorage<'a, Position>,
         ReadStorage<'a, Shape>,
         WriteStorage<'a, Velocity>,
+        ReadStorage<'a, Paddle>,
+        ReadStorage<'a, Goal>,
     );
 
-    fn run(&mut self, (state, mut positions, shapes, mut velocities): Self::SystemData) {
-        if let GameState(State::Idle) = *state {
-            return;
-        }
+    fn run(&mut self, (mut positions, shapes, mut velocities, paddles, goals): Self::SystemData) {
+        // edges leading to a goal let the ball through, the others are walls
+        let walls: Vec<Side> = Side::all()
+            .iter()
+            .cloned()
+            .filter(|side| !(&goals).join().any(|goal| goal.zone == Zone::Edge(*side)))
+            .collect();
 
-        for (position, shape, velocity) in (&mut positions, &shapes, &mut velocities).join() {
+        for (position, shape, velocity, paddle) in
+            (&mut positions, &shapes, &mut velocities, paddles.maybe()).join()
+        {
             match shape {
                 Shape::Circle { radius } => {
-                    if position.current.x - radius < 0.0 || position.current.x + radius > 1.0 {
-                        if position.current.x - radius < 0.0 {
-                            position.current.x = *radius;
+                    for side in &walls {
+                        let outward = side.outward();
+                        let overshoot = match side {
+                            Side::Top | Side::Right => position.current.dot(outward) - 1.0,
+                            Side::Bottom | Side::Left => position.current.dot(outward),
+                        } + radius;
+                        if overshoot > 0.0 {
+                            position.current += -outward * overshoot;
+                            if velocity.direction.dot(outward) > 0.0 {
+                                velocity.direction = velocity.direction.reflect(outward);
+                            }
                         }
-                        if position.current.x + radius > 1.0 {
-                            position.current.x = 1.0 - radius;
-                        }
-                        velocity.direction.x *= -1.0;
                     }
                 }
-                Shape::Rectangle { width, .. } => {
-                    if position.current.x - width * 0.5 < 0.0
-                        || position.current.x + width * 0.5 > 1.0
-                    {
-                        if position.current.x - width * 0.5 < 0.0 {
-                            position.current.x = width * 0.5;
-                        }
-                        if position.current.x + width * 0.5 > 1.0 {
-                            position.current.x = 1.0 - width * 0.5;
-                        }
-                        velocity.direction.x = 0.0;
+                Shape::Rectangle { width, height } => {
+                    let axis = paddle.map_or(Vector::new(1.0, 0.0), |p| p.side.axis());
+                    let half_length = Vector::new(*width, *height).dot(axis) * 0.5;
+                    let along = position.current.dot(axis);
+                    let clamped = num::clamp(along, half_length, 1.0 - half_length);
+                    if clamped != along {
+                        position.current += axis * (clamped - along);
+                        velocity.direction = Default::default();
                     }
                 }
             }
@@ -150,9 +350,12 @@ impl<'a> System<'a> for OutOfBound {
 
 pub struct CollisionDetection;
 
+impl RunsIn for CollisionDetection {
+    const STATES: &'static [State] = &[State::Running];
+}
+
 impl<'a> System<'a> for CollisionDetection {
     type SystemData = (
-        Read<'a, GameState>,
         Entities<'a>,
         ReadStorage<'a, Position>,
         WriteStorage<'a, Collision>,
@@ -163,29 +366,46 @@ impl<'a> System<'a> for CollisionDetection {
 
     fn run(
         &mut self,
-        (state, entities, positions, mut collisions, balls, paddles, shapes): Self::SystemData,
+        (entities, positions, mut collisions, balls, paddles, shapes): Self::SystemData,
     ) {
-        if let GameState(State::Idle) = *state {
-            return;
-        }
-
         for (entity, ball_pos, _, ball_shape) in (&entities, &positions, &balls, &shapes).join() {
             if let Shape::Circle { radius } = ball_shape {
                 let circle = Circle {
-                    center: ball_pos.current,
+                    center: ball_pos.previous,
                     radius: *radius,
                 };
-                for (paddle_pos, _, paddle_shape) in (&positions, &paddles, &shapes).join() {
+                let ball_displacement = ball_pos.current - ball_pos.previous;
+
+                let mut first_impact: Option<Collision> = None;
+                for (paddle, paddle_pos, _, paddle_shape) in
+                    (&entities, &positions, &paddles, &shapes).join()
+                {
                     if let Shape::Rectangle { width, height } = paddle_shape {
+                        // Sweep in the paddle's frame of reference so its own motion is accounted for
                         let rectangle = Rectangle::new(
-                            paddle_pos.current + Vector::new(-width * 0.5, -height * 0.5),
-                            paddle_pos.current + Vector::new(width * 0.5, height * 0.5),
+                            paddle_pos.previous + Vector::new(-width * 0.5, -height * 0.5),
+                            paddle_pos.previous + Vector::new(width * 0.5, height * 0.5),
                         );
-                        if check_collision(rectangle, circle) {
-                            collisions.insert(entity, Collision).unwrap();
+                        let paddle_displacement = paddle_pos.current - paddle_pos.previous;
+                        let displacement = ball_displacement - paddle_displacement;
+                        if let Some((time, mut contact)) =
+                            find_swept_contact(rectangle, circle, displacement)
+                        {
+                            if first_impact.as_ref().is_none_or(|first| time < first.time) {
+                                contact.point += paddle_displacement * time;
+                                first_impact = Some(Collision {
+                                    paddle,
+                                    time,
+                                    contact,
+                                });
+                            }
                         }
                     }
                 }
+
+                if let Some(collision) = first_impact {
+                    collisions.insert(entity, collision).unwrap();
+                }
             }
         }
     }
@@ -193,179 +413,582 @@ impl<'a> System<'a> for CollisionDetection {
 
 pub struct CollisionResolution;
 
+impl RunsIn for CollisionResolution {
+    const STATES: &'static [State] = &[State::Running];
+}
+
 impl<'a> System<'a> for CollisionResolution {
     type SystemData = (
-        Read<'a, GameState>,
         Read<'a, DeltaTime>,
+        Read<'a, BounceSettings>,
+        Read<'a, BallSettings>,
+        Write<'a, PaddleHits>,
         Entities<'a>,
         WriteStorage<'a, Collision>,
         WriteStorage<'a, Position>,
         WriteStorage<'a, Velocity>,
+        ReadStorage<'a, Shape>,
+        ReadStorage<'a, Paddle>,
         Read<'a, LazyUpdate>,
     );
 
     fn run(
         &mut self,
-        (state, delta, entities, collisions, mut positions, mut velocities, updater): Self::SystemData,
+        (
+            delta,
+            bounce,
+            ball,
+            mut hits,
+            entities,
+            collisions,
+            mut positions,
+            mut velocities,
+            shapes,
+            paddles,
+            updater,
+        ): Self::SystemData,
     ) {
-        if let GameState(State::Idle) = *state {
-            return;
-        }
-
-        for (entity, _, position, velocity) in
-            (&entities, &collisions, &mut positions, &mut velocities).join()
-        {
-            velocity.direction.y *= -1.0;
-            velocity.speed = velocity.max_speed.min(velocity.speed * 1.1);
-            position.current.y += velocity.direction.y * velocity.speed * delta.0;
+        for (entity, collision) in (&entities, &collisions).join() {
             updater.remove::<Collision>(entity);
+            hits.0 += 1;
+
+            let contact = collision.contact;
+            let time = collision.time;
+
+            let axis = paddles
+                .get(collision.paddle)
+                .map_or(Vector::new(1.0, 0.0), |paddle| paddle.side.axis());
+            let across = Vector::new(axis.y, axis.x);
+
+            // Where the ball struck along the paddle's face, from -1 (left or bottom edge)
+            // to 1 (right or top edge)
+            let mut strike = None;
+            if contact.normal.dot(across).abs() > contact.normal.dot(axis).abs() {
+                if let (Some(paddle_pos), Some(Shape::Rectangle { width, height })) = (
+                    positions.get(collision.paddle),
+                    shapes.get(collision.paddle),
+                ) {
+                    let paddle_center =
+                        paddle_pos.previous + (paddle_pos.current - paddle_pos.previous) * time;
+                    let half_length = Vector::new(*width, *height).dot(axis) * 0.5;
+                    let offset = (contact.point - paddle_center).dot(axis) / half_length;
+                    strike = Some(num::clamp(offset, -1.0, 1.0));
+                }
+            }
+            let paddle_motion = velocities
+                .get(collision.paddle)
+                .map_or(0.0, |v| v.direction.dot(axis) * v.speed / v.max_speed);
+
+            if let (Some(position), Some(velocity)) =
+                (positions.get_mut(entity), velocities.get_mut(entity))
+            {
+                // Rewind to the contact, bounce, then spend the rest of the frame moving away
+                let at_contact = position.previous + (position.current - position.previous) * time;
+                let separated = at_contact + contact.normal * contact.penetration;
+                let bounced = match strike {
+                    Some(strike) => {
+                        let angle =
+                            (strike + paddle_motion * bounce.paddle_influence) * bounce.max_angle;
+                        let angle = num::clamp(angle, -bounce.max_angle, bounce.max_angle);
+                        let away = across * contact.normal.dot(across).signum();
+                        velocity.direction = axis * angle.sin() + away * angle.cos();
+                        true
+                    }
+                    None if velocity.direction.dot(contact.normal) < 0.0 => {
+                        velocity.direction = velocity.direction.reflect(contact.normal);
+                        true
+                    }
+                    // already moving away from the paddle
+                    None => false,
+                };
+                if bounced {
+                    velocity.speed = velocity.max_speed.min(velocity.speed * ball.speed_up);
+                }
+                let remaining = (1.0 - time) * delta.0;
+                position.current = separated + velocity.direction * velocity.speed * remaining;
+            }
         }
     }
 }
 
 pub struct ScoreComputer;
 
+impl RunsIn for ScoreComputer {
+    const STATES: &'static [State] = &[State::Running];
+}
+
 impl<'a> System<'a> for ScoreComputer {
     type SystemData = (
-        Write<'a, GameState>,
-        WriteStorage<'a, Position>,
-        WriteStorage<'a, Velocity>,
+        Read<'a, StateMachine>,
+        Write<'a, MatchStatus>,
+        Entities<'a>,
+        ReadStorage<'a, Position>,
         ReadStorage<'a, Ball>,
+        ReadStorage<'a, Paddle>,
+        ReadStorage<'a, Goal>,
         WriteStorage<'a, Score>,
+        WriteStorage<'a, Lives>,
     );
 
     fn run(
         &mut self,
-        (mut state, mut positions, mut velocities, balls, mut scores): Self::SystemData,
+        (
+            machine,
+            mut status,
+            entities,
+            positions,
+            balls,
+            paddles,
+            goals,
+            mut scores,
+            mut lives,
+        ): Self::SystemData,
     ) {
-        if let GameState(State::Idle) = *state {
-            return;
+        let mut eliminated = None;
+        'outer: for (position, _) in (&positions, &balls).join() {
+            for goal in (&goals).join() {
+                if goal.zone.contains(position.current) {
+                    machine.request(State::Serving);
+                    // the player behind an edge goal lost the point even without lives
+                    let behind = match goal.zone {
+                        Zone::Edge(side) => (&paddles).join().find(|p| p.side == side),
+                        Zone::Area(_) => None,
+                    };
+                    status.last_point = Some(Point {
+                        winner: goal.scorer.and_then(|e| paddles.get(e)).map(|p| p.side),
+                        loser: goal
+                            .defender
+                            .and_then(|e| paddles.get(e))
+                            .or(behind)
+                            .map(|p| p.side),
+                    });
+                    if let Some(score) = goal.scorer.and_then(|s| scores.get_mut(s)) {
+                        score.current += 1;
+                    }
+                    if let Some(defender) = goal.defender {
+                        if let Some(lives) = lives.get_mut(defender) {
+                            lives.current = lives.current.saturating_sub(1);
+                            if lives.current == 0 {
+                                eliminated = Some(defender);
+                            }
+                        }
+                    }
+                    break 'outer;
+                }
+            }
         }
 
-        let mut scored = false;
-        'outter: for (position, _) in (&mut positions, &balls).join() {
-            for score in (&mut scores).join() {
-                if (score.trigger)(position.current) {
-                    *state = GameState(State::Idle);
-                    score.current += 1;
-                    scored = true;
-                    break 'outter;
+        // a player out of lives leaves the game and its side turns back into a wall
+        if let Some(eliminated) = eliminated {
+            for (entity, goal) in (&entities, &goals).join() {
+                if goal.defender == Some(eliminated) {
+                    entities.delete(entity).unwrap();
                 }
             }
+            entities.delete(eliminated).unwrap();
         }
+    }
+}
 
-        if scored {
-            for position in (&mut positions).join() {
-                position.current = position.default;
-            }
+/// Picks who serves, puts every entity back at its default position and aims the ball away
+/// from the server.
+pub struct PrepareServe;
+
+impl<'a> System<'a> for PrepareServe {
+    type SystemData = (
+        Read<'a, MatchRules>,
+        Read<'a, BallSettings>,
+        Write<'a, MatchStatus>,
+        Write<'a, Random>,
+        Entities<'a>,
+        ReadStorage<'a, Paddle>,
+        ReadStorage<'a, Input>,
+        ReadStorage<'a, FollowCursor>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Velocity>,
+        ReadStorage<'a, Ball>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            rules,
+            ball,
+            mut status,
+            mut random,
+            entities,
+            paddles,
+            inputs,
+            followers,
+            mut positions,
+            mut velocities,
+            balls,
+        ): Self::SystemData,
+    ) {
+        let sides: Vec<Side> = (&paddles).join().map(|paddle| paddle.side).collect();
+        let point = status.last_point.take();
+        let server = rules
+            .serve
+            .next_server(&sides, status.server, point)
+            .or_else(|| {
+                let index = random.gen_range(0.0, sides.len() as f64) as usize;
+                sides.get(index).cloned()
+            });
+        status.server = server;
+
+        let manual = rules.manual_serve
+            && (&entities, &paddles).join().any(|(e, paddle)| {
+                Some(paddle.side) == server
+                    && (inputs.get(e).is_some() || followers.get(e).is_some())
+            });
+        status.countdown = if manual { None } else { Some(SERVE_COUNTDOWN) };
+
+        for position in (&mut positions).join() {
+            position.reset();
+        }
+
+        for (velocity, _) in (&mut velocities, &balls).join() {
+            velocity.direction = serve_direction(&mut random, server);
+            velocity.speed = ball.speed;
+        }
+    }
+}
+
+/// Runs `PrepareServe` on `world`, unless the game is resuming from a pause.
+pub fn prepare_serve(world: &mut World) {
+    if world.read_resource::<StateMachine>().previous() != State::Paused {
+        PrepareServe.run_now(&world.res);
+    }
+}
+
+/// Serves the ball once the countdown is over.
+pub struct ServeCountdown;
+
+impl RunsIn for ServeCountdown {
+    const STATES: &'static [State] = &[State::Serving];
+}
 
-            for (velocity, _) in (&mut velocities, &balls).join() {
-                let dir = Vector::new(random::<f64>() * 2.0 - 1.0, random::<f64>() * 10.0 - 5.0);
-                velocity.direction = dir.normalize();
-                velocity.speed = BALL_DEFAULT_SPEED;
+impl<'a> System<'a> for ServeCountdown {
+    type SystemData = (
+        Read<'a, StateMachine>,
+        Read<'a, DeltaTime>,
+        Write<'a, MatchStatus>,
+    );
+
+    fn run(&mut self, (machine, delta, mut status): Self::SystemData) {
+        if let Some(countdown) = status.countdown {
+            let countdown = countdown - delta.0;
+            if countdown <= 0.0 {
+                status.countdown = None;
+                machine.request(State::Running);
+            } else {
+                status.countdown = Some(countdown);
             }
         }
     }
 }
 
-pub struct Render<'a> {
-    pub gl: GlGraphics,
-    pub glyphs: GlyphCache<'a>,
+/// Ends sets and the match following the `MatchRules`.
+pub struct Referee;
+
+impl RunsIn for Referee {
+    const STATES: &'static [State] = &[State::Running];
 }
 
-impl<'a, 'b> System<'a> for Render<'b> {
+impl<'a> System<'a> for Referee {
     type SystemData = (
-        ReadExpect<'a, GameArea>,
-        Read<'a, GameState>,
-        Write<'a, RenderEvent>,
-        ReadStorage<'a, Position>,
-        ReadStorage<'a, Shape>,
-        ReadStorage<'a, Score>,
+        Read<'a, StateMachine>,
+        Read<'a, MatchRules>,
+        Write<'a, MatchStatus>,
+        Read<'a, DeltaTime>,
+        ReadStorage<'a, Paddle>,
+        WriteStorage<'a, Score>,
+        ReadStorage<'a, Lives>,
     );
 
-    fn run(&mut self, (area, state, mut event, positions, shapes, scores): Self::SystemData) {
-        if let Some(args) = event.0 {
-            let glyphs = &mut self.glyphs;
-
-            self.gl.draw(args.viewport(), |context, graphics| {
-                clear(WHITE, graphics);
-                rectangle(
-                    BLACK,
-                    [0.0, 0.0, area.width, area.height],
-                    context.transform,
-                    graphics,
-                );
-
-                for (position, shape) in (&positions, &shapes).join() {
-                    match shape {
-                        Shape::Circle { radius } => {
-                            ellipse(
-                                WHITE,
-                                [
-                                    -radius * area.width,
-                                    -radius * area.width,
-                                    radius * 2.0 * area.width,
-                                    radius * 2.0 * area.width,
-                                ],
-                                context.transform.trans(
-                                    position.current.x * area.width,
-                                    (1.0 - position.current.y) * area.height,
-                                ),
-                                graphics,
-                            );
-                        }
-                        Shape::Rectangle { width, height } => {
-                            rectangle(
-                                WHITE,
-                                [
-                                    -width * 0.5 * area.width,
-                                    -height * 0.5 * area.height,
-                                    width * area.width,
-                                    height * area.height,
-                                ],
-                                context.transform.trans(
-                                    position.current.x * area.width,
-                                    (1.0 - position.current.y) * area.height,
-                                ),
-                                graphics,
-                            );
-                        }
-                    }
-                }
+    fn run(
+        &mut self,
+        (machine, rules, mut status, delta, paddles, mut scores, lives): Self::SystemData,
+    ) {
+        status.set_time += delta.0;
+
+        // with lives the last player standing wins, eliminated players are only removed
+        // from the world at the end of the tick
+        if (&lives).join().any(|lives| lives.current == 0) {
+            let mut alive = (&paddles, &lives)
+                .join()
+                .filter(|(_, lives)| lives.current > 0)
+                .map(|(paddle, _)| paddle.side);
+            let winner = alive.next();
+            if alive.next().is_none() {
+                status.winner = winner;
+                machine.request(State::GameOver);
+            }
+            return;
+        }
 
-                for score in (&scores).join() {
-                    let text_transform = context.transform.trans(
-                        score.position.x * area.width,
-                        (1.0 - score.position.y) * area.height + TEXT_SIZE as f64 * 0.5,
-                    );
-                    text(
-                        BLACK,
-                        TEXT_SIZE,
-                        &format!("{}", score.current),
-                        glyphs,
-                        text_transform,
-                        graphics,
-                    )
-                    .unwrap_or_else(|_| {});
-                }
+        let points: Vec<u32> = (&paddles, &scores)
+            .join()
+            .map(|(_, score)| score.current)
+            .collect();
+        let winner = match rules.set_winner(&points, status.set_time) {
+            Some(winner) => winner,
+            None => return,
+        };
+
+        let (paddle, score) = (&paddles, &mut scores).join().nth(winner).unwrap();
+        score.sets += 1;
+        if score.sets >= rules.sets_to_win() {
+            status.winner = Some(paddle.side);
+            machine.request(State::GameOver);
+            return;
+        }
 
-                if let GameState(State::Idle) = *state {
-                    let text_transform = context.transform.trans(
-                        area.width * 0.5 - 60.0,
-                        area.height * 0.5 + TEXT_SIZE as f64 * 0.5,
-                    );
-                    text(
-                        TEXT_COLOR,
-                        TEXT_SIZE,
-                        "Space to start",
-                        glyphs,
-                        text_transform,
-                        graphics,
-                    )
-                    .unwrap_or_else(|_| {});
-                }
-            });
+        for score in (&mut scores).join() {
+            score.current = 0;
         }
-        *event = RenderEvent(None);
+        status.set_time = 0.0;
+        // the time limit can also end a set in the middle of a rally
+        machine.request(State::Serving);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lets a ball moving along `direction` hit the top face of a bottom paddle at `x`, the
+    /// paddle moving along `paddle_direction` at its max speed, and returns the ball's
+    /// velocity after the bounce.
+    fn bounce_off_paddle(
+        x: f64,
+        normal: Vector,
+        direction: Vector,
+        paddle_direction: Vector,
+    ) -> (Vector, f64) {
+        let mut world = World::new();
+        world.register::<Position>();
+        world.register::<Velocity>();
+        world.register::<Shape>();
+        world.register::<Paddle>();
+        world.register::<Collision>();
+        world.add_resource(DeltaTime(1.0 / 120.0));
+        world.add_resource(BounceSettings::default());
+        world.add_resource(BallSettings::default());
+        world.add_resource(PaddleHits::default());
+
+        let paddle = world
+            .create_entity()
+            .with(Position::new(0.5, 0.025))
+            .with(Velocity::new(paddle_direction, 0.55))
+            .with(Shape::Rectangle {
+                width: 0.25,
+                height: 0.05,
+            })
+            .with(Paddle { side: Side::Bottom })
+            .build();
+        let ball = world
+            .create_entity()
+            .with(Position::new(x, 0.075))
+            .with(Velocity {
+                direction,
+                speed: 0.5,
+                max_speed: 2.0,
+            })
+            .with(Collision {
+                paddle,
+                time: 0.0,
+                contact: Contact {
+                    normal,
+                    penetration: 0.0,
+                    point: Vector::new(x, 0.05),
+                },
+            })
+            .build();
+
+        CollisionResolution.run_now(&world.res);
+        world.maintain();
+        let velocities = world.read_storage::<Velocity>();
+        let velocity = velocities.get(ball).unwrap();
+        (velocity.direction, velocity.speed)
+    }
+
+    #[test]
+    fn it_should_only_speed_up_balls_that_bounce() {
+        let still = Vector::default();
+        let (direction, speed) =
+            bounce_off_paddle(0.625, Vector::new(1.0, 0.0), Vector::new(-1.0, 0.0), still);
+        assert_eq!(Vector::new(1.0, 0.0), direction);
+        assert!((speed - 0.55).abs() < 1e-9);
+
+        // a ball already leaving the side of the paddle keeps its course
+        let (direction, speed) =
+            bounce_off_paddle(0.625, Vector::new(1.0, 0.0), Vector::new(1.0, 0.0), still);
+        assert_eq!(Vector::new(1.0, 0.0), direction);
+        assert_eq!(0.5, speed);
+    }
+
+    fn assert_leaves_at(angle: f64, direction: Vector) {
+        let expected = Vector::new(angle.sin(), angle.cos());
+        assert!(
+            (direction - expected).length() < 1e-9,
+            "{:?} does not leave at {} radians",
+            direction,
+            angle
+        );
+    }
+
+    #[test]
+    fn it_should_send_centre_hits_straight_back() {
+        let (direction, _) = bounce_off_paddle(
+            0.5,
+            Vector::new(0.0, 1.0),
+            Vector::new(0.6, -0.8),
+            Vector::default(),
+        );
+        assert_leaves_at(0.0, direction);
+    }
+
+    #[test]
+    fn it_should_angle_edge_hits_up_to_the_max_angle() {
+        let max_angle = BounceSettings::default().max_angle;
+        let up = Vector::new(0.0, 1.0);
+        let down = Vector::new(0.0, -1.0);
+        let still = Vector::default();
+
+        let (direction, _) = bounce_off_paddle(0.625, up, down, still);
+        assert_leaves_at(max_angle, direction);
+        let (direction, _) = bounce_off_paddle(0.375, up, down, still);
+        assert_leaves_at(-max_angle, direction);
+        let (direction, _) = bounce_off_paddle(0.5625, up, down, still);
+        assert_leaves_at(max_angle * 0.5, direction);
+    }
+
+    #[test]
+    fn it_should_bend_the_angle_with_the_paddle_movement() {
+        let bounce = BounceSettings::default();
+        let up = Vector::new(0.0, 1.0);
+        let down = Vector::new(0.0, -1.0);
+        let right = Vector::new(1.0, 0.0);
+
+        let (direction, _) = bounce_off_paddle(0.5, up, down, right);
+        assert_leaves_at(bounce.paddle_influence * bounce.max_angle, direction);
+        let (direction, _) = bounce_off_paddle(0.5, up, down, -right);
+        assert_leaves_at(-bounce.paddle_influence * bounce.max_angle, direction);
+
+        // the paddle movement cannot push an edge hit beyond the max angle
+        let (direction, _) = bounce_off_paddle(0.625, up, down, right);
+        assert_leaves_at(bounce.max_angle, direction);
+    }
+
+    /// A top paddle controlled by an AI and a ball at the center moving along `direction`.
+    fn ai_world(difficulty: Difficulty, seed: u64, direction: Vector) -> (World, Entity, Entity) {
+        let mut world = World::new();
+        world.register::<Position>();
+        world.register::<Velocity>();
+        world.register::<Shape>();
+        world.register::<Ball>();
+        world.register::<Paddle>();
+        world.register::<Ai>();
+        world.add_resource(DeltaTime(1.0 / 120.0));
+        world.add_resource(Random::new(seed));
+
+        let ball = world
+            .create_entity()
+            .with(Position::new(0.5, 0.5))
+            .with(Velocity::new(direction.normalize(), 0.5))
+            .with(Shape::Circle { radius: 0.025 })
+            .with(Ball)
+            .build();
+        let paddle = world
+            .create_entity()
+            .with(Position::new(0.5, 0.975))
+            .with(Velocity::new(Vector::default(), 0.55))
+            .with(Shape::Rectangle {
+                width: 0.25,
+                height: 0.05,
+            })
+            .with(Paddle { side: Side::Top })
+            .with(Ai::new(difficulty))
+            .build();
+        (world, ball, paddle)
+    }
+
+    fn ai_target(world: &World, paddle: Entity) -> f64 {
+        world
+            .read_storage::<Ai>()
+            .get(paddle)
+            .unwrap()
+            .target
+            .unwrap()
+    }
+
+    #[test]
+    fn it_should_move_towards_where_the_ball_will_cross_after_bouncing_off_a_wall() {
+        let (world, _, paddle) = ai_world(Difficulty::Hard, 1, Vector::new(2.0, 1.0));
+        AiControl.run_now(&world.res);
+
+        // the ball would cross the paddle's line at 1.35, beyond the right wall at 0.975
+        let target = ai_target(&world, paddle);
+        assert!((target - 0.6).abs() <= Difficulty::Hard.prediction_error());
+        let velocities = world.read_storage::<Velocity>();
+        assert_eq!(
+            Vector::new(1.0, 0.0),
+            velocities.get(paddle).unwrap().direction
+        );
+    }
+
+    #[test]
+    fn it_should_go_back_to_the_center_when_the_ball_moves_away() {
+        let (world, _, paddle) = ai_world(Difficulty::Hard, 1, Vector::new(2.0, -1.0));
+        AiControl.run_now(&world.res);
+        assert_eq!(0.5, ai_target(&world, paddle));
+    }
+
+    #[test]
+    fn it_should_only_look_at_the_ball_again_after_its_reaction_delay() {
+        let (world, ball, paddle) = ai_world(Difficulty::Normal, 1, Vector::new(2.0, 1.0));
+        AiControl.run_now(&world.res);
+        let first = ai_target(&world, paddle);
+
+        // the ball now heads to the other side, crossing the paddle's line around 0.4
+        world
+            .write_storage::<Velocity>()
+            .get_mut(ball)
+            .unwrap()
+            .direction = Vector::new(-2.0, 1.0).normalize();
+        let delay_ticks = (Difficulty::Normal.reaction_delay() * 120.0).round() as usize;
+        for _ in 0..delay_ticks - 2 {
+            AiControl.run_now(&world.res);
+        }
+        assert_eq!(first, ai_target(&world, paddle));
+        for _ in 0..4 {
+            AiControl.run_now(&world.res);
+        }
+        let target = ai_target(&world, paddle);
+        assert!((target - 0.4).abs() <= Difficulty::Normal.prediction_error());
+    }
+
+    #[test]
+    fn it_should_miss_more_and_move_slower_at_lower_difficulties() {
+        let misses: Vec<f64> = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard]
+            .iter()
+            .map(|difficulty| {
+                let mut largest_miss: f64 = 0.0;
+                for seed in 0..50 {
+                    let (world, _, paddle) = ai_world(*difficulty, seed, Vector::new(2.0, 1.0));
+                    AiControl.run_now(&world.res);
+                    let miss = (ai_target(&world, paddle) - 0.6).abs();
+                    assert!(miss <= difficulty.prediction_error());
+                    largest_miss = largest_miss.max(miss);
+
+                    let velocities = world.read_storage::<Velocity>();
+                    let speed = velocities.get(paddle).unwrap().speed;
+                    assert!((speed - 0.55 * difficulty.speed_factor()).abs() < 1e-9);
+                }
+                largest_miss
+            })
+            .collect();
+        assert!(misses[0] > misses[1] && misses[1] > misses[2]);
     }
 }
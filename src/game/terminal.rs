@@ -0,0 +1,220 @@
+//! Renderer drawing scenes with characters, for terminals.
+
+use super::render::{DrawCommand, Renderer, Viewport, BLACK};
+use std::io::Write;
+use std::ops::Range;
+
+/// Draws scenes as a grid of characters, each covering `cell_width` by `cell_height` pixels of
+/// the scene, framed by a one character margin.
+pub struct TerminalRenderer<W: Write> {
+    pub columns: usize,
+    pub rows: usize,
+    pub cell_width: f64,
+    pub cell_height: f64,
+    pub output: W,
+}
+
+impl<W: Write> TerminalRenderer<W> {
+    /// Fits a scene of `width` by `height` pixels in `columns` by `rows` characters.
+    pub fn new(width: u32, height: u32, columns: usize, rows: usize, output: W) -> Self {
+        TerminalRenderer {
+            columns,
+            rows,
+            cell_width: f64::from(width) / columns as f64,
+            cell_height: f64::from(height) / rows as f64,
+            output,
+        }
+    }
+
+    /// Returns the lines of characters drawing `commands`, margin included.
+    pub fn lines(&self, commands: &[DrawCommand]) -> Vec<String> {
+        let mut grid = Grid::new(self.columns + 2, self.rows + 2);
+        for command in commands {
+            match command {
+                DrawCommand::Clear(_) => grid = Grid::new(self.columns + 2, self.rows + 2),
+                DrawCommand::Rectangle { color, rect } => {
+                    let [x, y, width, height] = *rect;
+                    let mut columns = cells(x, x + width, self.cell_width);
+                    let mut rows = cells(y, y + height, self.cell_height);
+                    if *color == BLACK {
+                        // the dark background of the arena, framed by box-drawing characters
+                        grid.frame(columns, rows);
+                    } else if color[3] >= 1.0 {
+                        // rectangles thinner than a cell still cover the line of cells holding
+                        // their center
+                        if columns.start >= columns.end {
+                            let center = self.cell(x + width * 0.5, self.cell_width);
+                            columns = center..center + 1;
+                        }
+                        if rows.start >= rows.end {
+                            let center = self.cell(y + height * 0.5, self.cell_height);
+                            rows = center..center + 1;
+                        }
+                        for row in rows {
+                            for column in columns.clone() {
+                                grid.set(column, row, '█');
+                            }
+                        }
+                    }
+                }
+                DrawCommand::Circle { center, .. } => grid.set(
+                    self.cell(center[0], self.cell_width),
+                    self.cell(center[1], self.cell_height),
+                    '●',
+                ),
+                DrawCommand::Text { position, text, .. } => {
+                    // the line holding the bottom of the letters, just above the baseline
+                    let row = self.cell(position[1] - 1.0, self.cell_height);
+                    let mut column = self.cell(position[0], self.cell_width);
+                    // text starting right on the edge of a frame, like the scores next to the
+                    // arena, begins after it
+                    if grid.get(column, row).is_some_and(is_border) {
+                        column += 1;
+                    }
+                    for (i, c) in text.chars().enumerate() {
+                        grid.set(column + i as i64, row, c);
+                    }
+                }
+            }
+        }
+        grid.lines()
+    }
+
+    /// Cell holding the pixel at `position`, shifted by the margin.
+    fn cell(&self, position: f64, size: f64) -> i64 {
+        (position / size).floor() as i64 + 1
+    }
+}
+
+impl<W: Write> Renderer for TerminalRenderer<W> {
+    fn draw(&mut self, _viewport: Viewport, commands: &[DrawCommand]) {
+        // move to the top left corner and draw over the previous frame, lines being ended
+        // by "\r\n" for terminals in raw mode
+        let frame = format!("\x1b[H{}", self.lines(commands).join("\r\n"));
+        self.output
+            .write_all(frame.as_bytes())
+            .and_then(|_| self.output.flush())
+            .unwrap_or(());
+    }
+}
+
+/// Cells whose center lies between `start` and `end` pixels, shifted by the margin.
+fn cells(start: f64, end: f64, size: f64) -> Range<i64> {
+    let first = (start / size - 0.5).ceil() as i64;
+    let last = (end / size - 0.5).ceil() as i64;
+    first + 1..last + 1
+}
+
+fn is_border(c: char) -> bool {
+    "┌┐└┘─│".contains(c)
+}
+
+struct Grid {
+    width: usize,
+    height: usize,
+    cells: Vec<char>,
+}
+
+impl Grid {
+    fn new(width: usize, height: usize) -> Self {
+        Grid {
+            width,
+            height,
+            cells: vec![' '; width * height],
+        }
+    }
+
+    fn index(&self, column: i64, row: i64) -> Option<usize> {
+        if column >= 0 && row >= 0 && (column as usize) < self.width && (row as usize) < self.height
+        {
+            Some(row as usize * self.width + column as usize)
+        } else {
+            None
+        }
+    }
+
+    fn get(&self, column: i64, row: i64) -> Option<char> {
+        self.index(column, row).map(|i| self.cells[i])
+    }
+
+    /// Changes a cell, ignoring those out of the grid.
+    fn set(&mut self, column: i64, row: i64, c: char) {
+        if let Some(i) = self.index(column, row) {
+            self.cells[i] = c;
+        }
+    }
+
+    /// Clears the cells and draws a box around them.
+    fn frame(&mut self, columns: Range<i64>, rows: Range<i64>) {
+        let (left, right) = (columns.start - 1, columns.end);
+        let (top, bottom) = (rows.start - 1, rows.end);
+        for row in top..=bottom {
+            for column in left..=right {
+                let c = match (column, row) {
+                    (c, r) if c == left && r == top => '┌',
+                    (c, r) if c == right && r == top => '┐',
+                    (c, r) if c == left && r == bottom => '└',
+                    (c, r) if c == right && r == bottom => '┘',
+                    (_, r) if r == top || r == bottom => '─',
+                    (c, _) if c == left || c == right => '│',
+                    _ => ' ',
+                };
+                self.set(column, row, c);
+            }
+        }
+    }
+
+    fn lines(&self) -> Vec<String> {
+        self.cells
+            .chunks(self.width)
+            .map(|line| line.iter().collect())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::render::WHITE;
+
+    #[test]
+    fn it_should_frame_the_arena_and_place_entities_in_cells() {
+        let renderer = TerminalRenderer::new(50, 30, 5, 3, Vec::new());
+        let lines = renderer.lines(&[
+            DrawCommand::Clear(WHITE),
+            DrawCommand::Rectangle {
+                color: BLACK,
+                rect: [0.0, 0.0, 30.0, 30.0],
+            },
+            DrawCommand::Rectangle {
+                color: WHITE,
+                rect: [0.0, 27.0, 20.0, 3.0],
+            },
+            DrawCommand::Circle {
+                color: WHITE,
+                center: [15.0, 15.0],
+                radius: 1.0,
+            },
+            DrawCommand::Text {
+                color: BLACK,
+                size: 10,
+                position: [30.0, 10.0],
+                text: "7".to_string(),
+            },
+        ]);
+        assert_eq!(
+            vec!["┌───┐  ", "│   │7 ", "│ ● │  ", "│██ │  ", "└───┘  "],
+            lines
+        );
+    }
+
+    #[test]
+    fn it_should_redraw_from_the_top_left_corner() {
+        let mut renderer = TerminalRenderer::new(10, 10, 1, 1, Vec::new());
+        renderer.draw(Viewport::new(10, 10), &[DrawCommand::Clear(WHITE)]);
+        assert_eq!(
+            "\x1b[H   \r\n   \r\n   ",
+            String::from_utf8(renderer.output).unwrap()
+        );
+    }
+}
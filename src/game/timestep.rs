@@ -0,0 +1,74 @@
+/// Number of simulation ticks per second used unless configured otherwise.
+pub const DEFAULT_TICK_RATE: u32 = 120;
+
+/// Maximum number of ticks run for a single frame, so that a long stall does not
+/// make the simulation try to catch up forever.
+const MAX_TICKS_PER_FRAME: u32 = 8;
+
+/// Accumulates the variable frame time and converts it into a number of
+/// constant simulation ticks.
+pub struct FixedTimestep {
+    step: f64,
+    accumulator: f64,
+}
+
+impl FixedTimestep {
+    pub fn new(tick_rate: u32) -> Self {
+        FixedTimestep {
+            step: 1.0 / f64::from(tick_rate),
+            accumulator: 0.0,
+        }
+    }
+
+    /// Duration of a tick in seconds.
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    /// Adds `elapsed` seconds and returns how many ticks should run now.
+    pub fn advance(&mut self, elapsed: f64) -> u32 {
+        self.accumulator += elapsed;
+        let mut ticks = 0;
+        while self.accumulator >= self.step && ticks < MAX_TICKS_PER_FRAME {
+            self.accumulator -= self.step;
+            ticks += 1;
+        }
+        if ticks == MAX_TICKS_PER_FRAME {
+            self.accumulator = self.accumulator.min(self.step);
+        }
+        ticks
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        FixedTimestep::new(DEFAULT_TICK_RATE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_not_tick_before_a_full_step() {
+        let mut timestep = FixedTimestep::new(100);
+        assert_eq!(0, timestep.advance(0.005));
+        assert_eq!(1, timestep.advance(0.005));
+    }
+
+    #[test]
+    fn it_should_carry_remaining_time_over() {
+        let mut timestep = FixedTimestep::new(100);
+        assert_eq!(2, timestep.advance(0.025));
+        assert_eq!(1, timestep.advance(0.005));
+    }
+
+    #[test]
+    fn it_should_cap_ticks_after_a_stall() {
+        let mut timestep = FixedTimestep::new(100);
+        assert_eq!(MAX_TICKS_PER_FRAME, timestep.advance(10.0));
+        assert_eq!(1, timestep.advance(0.0));
+        assert_eq!(0, timestep.advance(0.0));
+    }
+}
@@ -0,0 +1,376 @@
+//! Runs whole matches without a window, as fast as possible.
+
+use crate::config::ArenaConfig;
+use crate::game::components::{Ball, Lives, Paddle, Position, Score, Side, Velocity};
+use crate::game::replay::{Playback, Replay, ReplaySettings};
+use crate::game::resources::{MatchStatus, PaddleHits};
+use crate::game::{Game, GameBuilder, State};
+use serde_derive::Serialize;
+use specs::prelude::*;
+
+pub struct MatchOptions {
+    /// Ticks after which the match is stopped even if nobody won.
+    pub max_ticks: u64,
+    /// Size of the arena in the scenes of the match.
+    pub arena: ArenaConfig,
+}
+
+/// Everything that happened between a serve and the next point.
+#[derive(Debug, Copy, Clone, Serialize)]
+pub struct Rally {
+    pub hits: u64,
+    pub ticks: u64,
+    pub mean_ball_speed: f64,
+    pub max_ball_speed: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchResult {
+    pub seed: u64,
+    /// Points of each player in the last set played.
+    pub top_score: u32,
+    pub bottom_score: u32,
+    /// Sets won by each player.
+    pub top_sets: u32,
+    pub bottom_sets: u32,
+    /// Lives left to each player in the order of the players, when playing with lives.
+    pub lives: Vec<u32>,
+    pub winner: Option<Side>,
+    pub ticks: u64,
+    pub finished: bool,
+    pub rallies: Vec<Rally>,
+}
+
+/// Aggregated statistics over several matches.
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub matches: usize,
+    pub top_wins: usize,
+    pub bottom_wins: usize,
+    pub unfinished: usize,
+    pub rallies: usize,
+    pub mean_rally_hits: f64,
+    pub longest_rally_hits: u64,
+    pub mean_rally_seconds: f64,
+    pub mean_ball_speed: f64,
+    pub max_ball_speed: f64,
+}
+
+/// Plays a match until a player wins it following `settings.rules` or `options.max_ticks`
+/// ticks ran.
+///
+/// The ball is served as soon as it is reset, skipping the serve countdown. Paddles without an AI only move when
+/// `script` holds inputs for them.
+pub fn run_match(
+    seed: u64,
+    settings: &ReplaySettings,
+    options: &MatchOptions,
+    script: Option<&Replay>,
+) -> MatchResult {
+    run_match_observed(seed, settings, options, script, |_| {})
+}
+
+/// Plays a match like `run_match`, calling `observe` before the first tick and after each
+/// tick. It may command the agents of the game for the next tick.
+pub fn run_match_observed<F>(
+    seed: u64,
+    settings: &ReplaySettings,
+    options: &MatchOptions,
+    script: Option<&Replay>,
+    mut observe: F,
+) -> MatchResult
+where
+    F: FnMut(&mut Game),
+{
+    let mut game = GameBuilder::new()
+        .seed(seed)
+        .replay_settings(settings)
+        .arena(options.arena.width, options.arena.height)
+        .build();
+    let mut script = script.map(|replay| Playback::new(replay.clone()));
+
+    let mut rallies = Vec::new();
+    let mut rally = RallyTracker::default();
+    let mut scores = read_scores(&game.world);
+    let mut lives = read_lives(&game.world, settings);
+    observe(&mut game);
+
+    loop {
+        let tick = game.tick();
+        let over = game.state() == State::GameOver;
+        if over || tick >= options.max_ticks {
+            let ((top_score, top_sets), (bottom_score, bottom_sets)) = scores;
+            return MatchResult {
+                seed,
+                top_score,
+                bottom_score,
+                top_sets,
+                bottom_sets,
+                lives,
+                winner: game.world.read_resource::<MatchStatus>().winner,
+                ticks: tick,
+                finished: over,
+                rallies,
+            };
+        }
+
+        if let Some(ref mut script) = script {
+            for input in script.inputs_for(tick) {
+                game.apply_input(input.kind);
+            }
+        }
+        if let State::Menu | State::Serving = game.state() {
+            game.serve_now();
+        }
+        game.step();
+        observe(&mut game);
+
+        if game.state() == State::Running {
+            rally.track(&game.world);
+        }
+        // a rally ends with a point, or with a life lost when playing with lives
+        let (new_scores, new_lives) = (read_scores(&game.world), read_lives(&game.world, settings));
+        if new_scores != scores || new_lives != lives {
+            scores = new_scores;
+            lives = new_lives;
+            rallies.push(rally.finish(&game.world));
+        }
+    }
+}
+
+/// Aggregates the results of several matches.
+pub fn summarize(results: &[MatchResult], tick_rate: u32) -> Summary {
+    let rallies: Vec<&Rally> = results.iter().flat_map(|r| r.rallies.iter()).collect();
+    let rally_count = rallies.len().max(1) as f64;
+    let total_ticks: u64 = rallies.iter().map(|r| r.ticks).sum();
+    let total_speed: f64 = rallies
+        .iter()
+        .map(|r| r.mean_ball_speed * r.ticks as f64)
+        .sum();
+
+    Summary {
+        matches: results.len(),
+        top_wins: results
+            .iter()
+            .filter(|r| r.winner == Some(Side::Top))
+            .count(),
+        bottom_wins: results
+            .iter()
+            .filter(|r| r.winner == Some(Side::Bottom))
+            .count(),
+        unfinished: results.iter().filter(|r| !r.finished).count(),
+        rallies: rallies.len(),
+        mean_rally_hits: rallies.iter().map(|r| r.hits as f64).sum::<f64>() / rally_count,
+        longest_rally_hits: rallies.iter().map(|r| r.hits).max().unwrap_or(0),
+        mean_rally_seconds: total_ticks as f64 / f64::from(tick_rate) / rally_count,
+        mean_ball_speed: total_speed / (total_ticks.max(1) as f64),
+        max_ball_speed: rallies.iter().map(|r| r.max_ball_speed).fold(0.0, f64::max),
+    }
+}
+
+/// Returns the points in the current set and the sets won of the top and bottom paddles.
+fn read_scores(world: &World) -> ((u32, u32), (u32, u32)) {
+    let positions = world.read_storage::<Position>();
+    let scores = world.read_storage::<Score>();
+    let mut result = ((0, 0), (0, 0));
+    for (position, score) in (&positions, &scores).join() {
+        if position.default.y > 0.5 {
+            result.0 = (score.current, score.sets);
+        } else {
+            result.1 = (score.current, score.sets);
+        }
+    }
+    result
+}
+
+/// Returns the lives left to each player in the order of the players, eliminated players
+/// having none, or nothing when the match is not played with lives.
+fn read_lives(world: &World, settings: &ReplaySettings) -> Vec<u32> {
+    if settings.lives.is_none() {
+        return Vec::new();
+    }
+    let paddles = world.read_storage::<Paddle>();
+    let lives = world.read_storage::<Lives>();
+    settings
+        .players
+        .iter()
+        .map(|player| {
+            (&paddles, &lives)
+                .join()
+                .find(|(paddle, _)| paddle.side == player.side)
+                .map_or(0, |(_, lives)| lives.current)
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct RallyTracker {
+    first_hit: u64,
+    ticks: u64,
+    total_speed: f64,
+    max_speed: f64,
+}
+
+impl RallyTracker {
+    fn track(&mut self, world: &World) {
+        let velocities = world.read_storage::<Velocity>();
+        let balls = world.read_storage::<Ball>();
+        for (velocity, _) in (&velocities, &balls).join() {
+            self.total_speed += velocity.speed;
+            self.max_speed = self.max_speed.max(velocity.speed);
+        }
+        self.ticks += 1;
+    }
+
+    fn finish(&mut self, world: &World) -> Rally {
+        let hits = world.read_resource::<PaddleHits>().0;
+        let rally = Rally {
+            hits: hits - self.first_hit,
+            ticks: self.ticks,
+            mean_ball_speed: self.total_speed / self.ticks.max(1) as f64,
+            max_ball_speed: self.max_speed,
+        };
+        *self = RallyTracker {
+            first_hit: hits,
+            ..Default::default()
+        };
+        rally
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ArenaConfig;
+    use crate::game::builder::{Controller, Player};
+    use crate::game::components::Difficulty;
+    use crate::game::input::InputConfig;
+    use crate::game::render::{DrawCommand, BLACK};
+    use crate::game::resources::{BallSettings, BounceSettings, MatchRules, PaddleSettings};
+
+    fn settings() -> ReplaySettings {
+        ReplaySettings {
+            tick_rate: 120,
+            players: vec![
+                Player {
+                    side: Side::Top,
+                    controller: Controller::Ai(Difficulty::Easy),
+                },
+                Player {
+                    side: Side::Bottom,
+                    controller: Controller::Ai(Difficulty::Hard),
+                },
+            ],
+            lives: None,
+            rules: MatchRules {
+                points: 3,
+                ..Default::default()
+            },
+            ball: BallSettings::default(),
+            paddle: PaddleSettings::default(),
+            bounce: BounceSettings::default(),
+            bindings: InputConfig::default(),
+        }
+    }
+
+    fn options() -> MatchOptions {
+        MatchOptions {
+            max_ticks: 120 * 600,
+            arena: ArenaConfig::default(),
+        }
+    }
+
+    #[test]
+    fn it_should_play_the_same_match_twice() {
+        let first = run_match(7, &settings(), &options(), None);
+        let second = run_match(7, &settings(), &options(), None);
+        assert_eq!(first.ticks, second.ticks);
+        assert_eq!(first.top_score, second.top_score);
+        assert_eq!(first.bottom_score, second.bottom_score);
+        assert_eq!(first.winner, second.winner);
+        assert_eq!(first.rallies.len(), second.rallies.len());
+    }
+
+    #[test]
+    fn it_should_count_the_sets_won() {
+        let mut settings = settings();
+        settings.rules.best_of = 3;
+        let result = run_match(7, &settings, &options(), None);
+        assert!(result.finished);
+        let (winner_sets, loser_sets) = match result.winner {
+            Some(Side::Top) => (result.top_sets, result.bottom_sets),
+            _ => (result.bottom_sets, result.top_sets),
+        };
+        assert_eq!(2, winner_sets);
+        assert!(loser_sets < 2);
+        // each set lasts at least 3 rallies
+        assert!(result.rallies.len() >= 3 * (winner_sets + loser_sets) as usize);
+    }
+
+    #[test]
+    fn it_should_count_the_lives_lost_in_a_match_with_lives() {
+        let mut settings = settings();
+        for side in &[Side::Left, Side::Right] {
+            settings.players.push(Player {
+                side: *side,
+                controller: Controller::Ai(Difficulty::Normal),
+            });
+        }
+        settings.lives = Some(2);
+        let result = run_match(7, &settings, &options(), None);
+
+        assert!(result.finished);
+        assert_eq!(4, result.lives.len());
+        let alive: Vec<usize> = (0..4).filter(|i| result.lives[*i] > 0).collect();
+        assert_eq!(1, alive.len());
+        assert_eq!(Some(settings.players[alive[0]].side), result.winner);
+        let lost: u32 = result.lives.iter().map(|lives| 2 - lives).sum();
+        assert_eq!(lost as usize, result.rallies.len());
+    }
+
+    #[test]
+    fn it_should_draw_the_arena_of_the_options() {
+        let options = MatchOptions {
+            arena: ArenaConfig {
+                width: 100.0,
+                height: 150.0,
+            },
+            ..options()
+        };
+        let mut arena = None;
+        run_match_observed(7, &settings(), &options, None, |game| {
+            arena = arena.or_else(|| {
+                game.scene().into_iter().find_map(|command| match command {
+                    DrawCommand::Rectangle { color, rect } if color == BLACK => Some(rect),
+                    _ => None,
+                })
+            });
+        });
+        assert_eq!(Some([0.0, 0.0, 100.0, 150.0]), arena);
+    }
+
+    #[test]
+    fn it_should_stop_when_a_player_wins() {
+        let result = run_match(7, &settings(), &options(), None);
+        assert!(result.finished);
+        assert_eq!(3, result.top_score.max(result.bottom_score));
+        let winner = if result.top_score > result.bottom_score {
+            Side::Top
+        } else {
+            Side::Bottom
+        };
+        assert_eq!(Some(winner), result.winner);
+        assert_eq!(
+            (result.top_score + result.bottom_score) as usize,
+            result.rallies.len()
+        );
+    }
+
+    #[test]
+    fn it_should_serve_without_a_countdown() {
+        let result = run_match(7, &settings(), &options(), None);
+        let rally_ticks: u64 = result.rallies.iter().map(|rally| rally.ticks).sum();
+        // only the ticks scoring a point are spent outside of a rally
+        assert!(result.ticks <= rally_ticks + result.rallies.len() as u64);
+    }
+}
@@ -1,2 +1,7 @@
+pub mod bot;
+pub mod cli;
+pub mod config;
+pub mod env;
+pub mod game;
+pub mod headless;
 pub mod math;
-pub mod game;
\ No newline at end of file
@@ -1,124 +1,225 @@
-use crate::components::*;
-use crate::resources::{DeltaTime, GameArea, GameState};
-use crate::systems::*;
+use crate::input::{InputConfig, InputKind};
+use crate::opengl::GlRenderer;
+use crate::render::Viewport;
+use crate::replay::{Playback, Replay, ReplaySettings};
+use crate::timestep::FixedTimestep;
 use glutin_window::GlutinWindow as Window;
 use opengl_graphics::{GlGraphics, GlyphCache, OpenGL, TextureSettings};
 use piston::event_loop::{EventLoop, EventSettings, Events};
-use piston::input::{Button, Key, PressEvent, ReleaseEvent, RenderEvent, UpdateEvent};
-use piston::window::WindowSettings;
+use piston::input::{
+    Button, Key, MouseCursorEvent, MouseRelativeEvent, PressEvent, ReleaseEvent, RenderEvent,
+    UpdateEvent,
+};
+use piston::window::{Window as _, WindowSettings};
+use pong::cli::{fail, Args, USAGE};
+use pong::config::{ArenaConfig, Config};
 use pong::game::*;
-use pong::math::Vector;
-use rand::random;
-use specs::prelude::*;
+use pong::headless::{run_match, MatchOptions};
 
-const WND_WIDTH: u32 = 250;
-const WND_HEIGHT: u32 = 300;
-const AREA_WIDTH: f64 = 200.0;
-const AREA_HEIGHT: f64 = 300.0;
+/// Seconds skipped when seeking through a replay.
+const SEEK_STEP: f64 = 5.0;
+
+/// Seconds after which a headless match is stopped even if nobody won.
+const HEADLESS_MAX_SECONDS: f64 = 600.0;
 
 fn main() {
+    let args = Args::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        std::process::exit(2);
+    });
+    if args.help {
+        println!("{}", USAGE);
+        return;
+    }
+    let config = Config::load_or_default(args.config.as_deref()).unwrap_or_else(|e| fail(&e));
+
+    let mut playback = args.playback.as_ref().map(|path| {
+        let replay = Replay::load(path)
+            .unwrap_or_else(|e| fail(&format!("Failed to load replay '{}': {}", path, e)));
+        Playback::new(replay)
+    });
+    let (seed, settings) = match playback {
+        Some(ref playback) => (playback.replay().seed, playback.replay().settings.clone()),
+        None => (
+            args.seed.unwrap_or_else(rand::random),
+            args.settings(
+                &config,
+                InputConfig::load_or_default(&args.bindings).unwrap_or_else(|e| fail(&e)),
+            ),
+        ),
+    };
+
+    if args.headless {
+        let options = MatchOptions {
+            max_ticks: (HEADLESS_MAX_SECONDS * f64::from(settings.tick_rate)) as u64,
+            arena: config.arena,
+        };
+        let script = playback.as_ref().map(|playback| playback.replay());
+        let result = run_match(seed, &settings, &options, script);
+        println!("{}", serde_json::to_string_pretty(&result).unwrap());
+        return;
+    }
+
+    println!("Seed: {}", seed);
+    let mut recording = args
+        .record
+        .as_ref()
+        .map(|_| Replay::new(seed, settings.clone()));
+
     let open_gl = OpenGL::V3_2;
-    let mut window: Window = WindowSettings::new("Pong", [WND_WIDTH, WND_HEIGHT])
+    let size = args
+        .window
+        .unwrap_or((config.window.width, config.window.height));
+    let mut window: Window = WindowSettings::new("Pong", [size.0, size.1])
         .opengl(open_gl)
-        .exit_on_esc(true)
+        .exit_on_esc(false)
         .resizable(false)
+        .fullscreen(args.fullscreen)
         .build()
         .unwrap();
     let mut events = Events::new(EventSettings::new().ups_reset(0));
+    // the arena keeps its place in the window whatever the size of the window
+    let size = window.size();
+    let arena = config.arena_in(size.width, size.height);
 
-    let mut world = World::new();
-    world.add_resource(GameArea {
-        width: AREA_WIDTH,
-        height: AREA_HEIGHT,
-    });
-    let mut dispatcher = DispatcherBuilder::new()
-        .with(InputUpdate, "input_update", &[])
-        .with(InputApply, "input_apply", &["input_update"])
-        .with(Movement, "movement", &["input_apply"])
-        .with(OutOfBound, "oob", &["movement"])
-        .with(CollisionDetection, "collision_detection", &["movement"])
-        .with(
-            CollisionResolution,
-            "collision_resolution",
-            &["collision_detection"],
-        )
-        .with(ScoreComputer, "score_computer", &[])
-        .with_thread_local(Render {
-            gl: GlGraphics::new(open_gl),
-            glyphs: GlyphCache::new("assets/arial.ttf", (), TextureSettings::new()).unwrap(),
-        })
-        .build();
-    dispatcher.setup(&mut world.res);
-
-    // ball
-    world
-        .create_entity()
-        .with(Position::new(0.5, 0.5))
-        .with(Velocity {
-            direction: Vector::new(random::<f64>() * 2.0 - 1.0, random::<f64>() * 10.0 - 5.0)
-                .normalize(),
-            speed: BALL_DEFAULT_SPEED,
-            max_speed: 2.0,
-        })
-        .with(Ball)
-        .with(Shape::Circle { radius: 0.025 })
-        .build();
-
-    // top paddle
-    world
-        .create_entity()
-        .with(Position::new(0.5, 0.975))
-        .with(Velocity::new(Default::default(), 0.55))
-        .with(Paddle)
-        .with(Shape::Rectangle {
-            width: 0.25,
-            height: 0.05,
-        })
-        .with(Input::new(Key::Q, Key::D))
-        .with(Score::new(|v| v.y < 0.0, Vector::new(1.01, 0.95)))
-        .build();
-
-    // bottom paddle
-    world
-        .create_entity()
-        .with(Position::new(0.5, 0.025))
-        .with(Velocity::new(Default::default(), 0.55))
-        .with(Paddle)
-        .with(Shape::Rectangle {
-            width: 0.25,
-            height: 0.05,
-        })
-        .with(Input::new(Key::Left, Key::Right))
-        .with(Score::new(|v| v.y > 1.0, Vector::new(1.01, 0.05)))
-        .build();
+    // bindings changed while replaying are not saved
+    let bindings_path = match playback {
+        Some(_) => None,
+        None => Some(args.bindings.clone()),
+    };
+    let mut game = new_game(seed, &settings, arena, open_gl, bindings_path);
+    let mut timestep = FixedTimestep::new(settings.tick_rate);
 
     while let Some(event) = events.next(&mut window) {
-        if let Some(args) = event.press_args() {
-            *world.write_resource::<crate::resources::PressEvent>() =
-                crate::resources::PressEvent(Some(args));
-        }
-
-        if let Some(args) = event.release_args() {
-            match args {
-                Button::Keyboard(Key::Space) => {
-                    *world.write_resource::<GameState>() = GameState(State::Running);
+        if let Some(button) = event.press_args() {
+            // the back action pauses a match in progress or leaves the bindings screen, and
+            // only quits from the other screens, while Esc always leaves a replay
+            let quits = match playback {
+                Some(_) => button == Button::Keyboard(Key::Escape),
+                None => game.quits_on(button),
+            };
+            if quits {
+                window.set_should_close(true);
+                continue;
+            }
+            match playback {
+                Some(ref mut playback) => {
+                    let tick = game.tick();
+                    let seek_ticks = (SEEK_STEP * f64::from(settings.tick_rate)) as u64;
+                    match button {
+                        Button::Keyboard(Key::Space) => playback.paused = !playback.paused,
+                        Button::Keyboard(Key::Up) => playback.set_speed(playback.speed() * 2.0),
+                        Button::Keyboard(Key::Down) => playback.set_speed(playback.speed() * 0.5),
+                        Button::Keyboard(Key::Right) => {
+                            seek(&mut game, playback, tick + seek_ticks)
+                        }
+                        Button::Keyboard(Key::Left) => {
+                            game = new_game(seed, &settings, arena, open_gl, None);
+                            playback.rewind();
+                            seek(&mut game, playback, tick.saturating_sub(seek_ticks));
+                        }
+                        _ => {}
+                    }
+                    println!(
+                        "Replay at tick {} - speed x{}{}",
+                        game.tick(),
+                        playback.speed(),
+                        if playback.paused { " - paused" } else { "" }
+                    );
                 }
-                _ => {
-                    *world.write_resource::<crate::resources::ReleaseEvent>() =
-                        crate::resources::ReleaseEvent(Some(args));
+                None => {
+                    if let Some(ref mut recording) = recording {
+                        recording.record(game.tick(), InputKind::Press(button));
+                    }
+                    game.apply_input(InputKind::Press(button));
                 }
             }
         }
 
+        let released = event.release_args().map(InputKind::Release);
+        let moved = event
+            .mouse_relative_args()
+            .map(|[dx, dy]| InputKind::MouseMotion(dx, dy));
+        let cursor = event
+            .mouse_cursor_args()
+            .map(|[x, y]| InputKind::MouseCursor(x, y));
+        if let (Some(kind), None) = (released.or(moved).or(cursor), &playback) {
+            if let Some(ref mut recording) = recording {
+                recording.record(game.tick(), kind);
+            }
+            game.apply_input(kind);
+        }
+
+        // rendering runs once per frame, outside of the fixed simulation ticks
         if let Some(args) = event.render_args() {
-            *world.write_resource::<crate::resources::RenderEvent>() =
-                crate::resources::RenderEvent(Some(args));
+            game.render(Viewport {
+                width: args.width,
+                height: args.height,
+                draw_width: args.draw_width,
+                draw_height: args.draw_height,
+            });
         }
 
         if let Some(args) = event.update_args() {
-            *world.write_resource::<DeltaTime>() = DeltaTime(args.dt);
-            dispatcher.dispatch(&mut world.res);
-            world.maintain();
+            let elapsed = match playback {
+                Some(ref playback) if playback.paused => 0.0,
+                Some(ref playback) => args.dt * playback.speed(),
+                None => args.dt,
+            };
+            for _ in 0..timestep.advance(elapsed) {
+                if let Some(ref mut playback) = playback {
+                    for input in playback.inputs_for(game.tick()) {
+                        game.apply_input(input.kind);
+                    }
+                }
+                game.step();
+            }
+        }
+    }
+
+    if let (Some(path), Some(recording)) = (args.record, recording) {
+        match recording.save(&path) {
+            Ok(()) => println!("Replay saved to {}", path),
+            Err(e) => eprintln!("Failed to save replay to '{}': {}", path, e),
+        }
+    }
+}
+
+/// Creates a game that can be rendered in the window, saving its bindings to `bindings_path`
+/// each time the bindings screen is left.
+fn new_game(
+    seed: u64,
+    settings: &ReplaySettings,
+    arena: ArenaConfig,
+    open_gl: OpenGL,
+    bindings_path: Option<String>,
+) -> Game<'static, 'static> {
+    GameBuilder::new()
+        .seed(seed)
+        .replay_settings(settings)
+        .menu()
+        .on_exit(State::Rebinding, move |world| {
+            if let Some(ref path) = bindings_path {
+                match world.read_resource::<InputConfig>().save(path) {
+                    Ok(()) => println!("Bindings saved to {}", path),
+                    Err(e) => eprintln!("Failed to save bindings to '{}': {}", path, e),
+                }
+            }
+        })
+        .arena(arena.width, arena.height)
+        .render(GlRenderer {
+            gl: GlGraphics::new(open_gl),
+            glyphs: GlyphCache::new("assets/arial.ttf", (), TextureSettings::new()).unwrap(),
+        })
+        .build()
+}
+
+/// Plays the replay as fast as possible until `target` is the next tick to run.
+fn seek(game: &mut Game, playback: &mut Playback, target: u64) {
+    while game.tick() < target {
+        for input in playback.inputs_for(game.tick()) {
+            game.apply_input(input.kind);
         }
+        game.step();
     }
 }
@@ -1,7 +1,8 @@
 use num;
-use std::ops::{Add, AddAssign, Mul};
+use serde_derive::{Deserialize, Serialize};
+use std::ops::{Add, AddAssign, Mul, Neg, Sub};
 
-#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Default)]
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Default, Serialize, Deserialize)]
 pub struct Vector {
     pub x: f64,
     pub y: f64,
@@ -9,7 +10,7 @@ pub struct Vector {
 
 impl Vector {
     pub fn new(x: f64, y: f64) -> Self {
-        Vector {x, y}
+        Vector { x, y }
     }
 
     pub fn length(self) -> f64 {
@@ -23,6 +24,15 @@ impl Vector {
             y: self.y / length,
         }
     }
+
+    pub fn dot(self, other: Vector) -> f64 {
+        self.x * other.x + self.y * other.y
+    }
+
+    /// Mirrors the vector against a surface of unit `normal`.
+    pub fn reflect(self, normal: Vector) -> Self {
+        self - normal * (2.0 * self.dot(normal))
+    }
 }
 
 impl Add for Vector {
@@ -38,8 +48,30 @@ impl Add for Vector {
 
 impl AddAssign for Vector {
     fn add_assign(&mut self, other: Vector) {
-        (*self).x += other.x;
-        (*self).y += other.y;
+        self.x += other.x;
+        self.y += other.y;
+    }
+}
+
+impl Sub for Vector {
+    type Output = Vector;
+
+    fn sub(self, other: Vector) -> Vector {
+        Vector {
+            x: self.x - other.x,
+            y: self.y - other.y,
+        }
+    }
+}
+
+impl Neg for Vector {
+    type Output = Vector;
+
+    fn neg(self) -> Vector {
+        Vector {
+            x: -self.x,
+            y: -self.y,
+        }
     }
 }
 
@@ -54,7 +86,21 @@ impl Mul<f64> for Vector {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Maps `value` into `[min, max]` as if it kept bouncing between both bounds.
+pub fn bounce_within(value: f64, min: f64, max: f64) -> f64 {
+    let span = max - min;
+    if span <= 0.0 {
+        return min;
+    }
+    let offset = (value - min).rem_euclid(2.0 * span);
+    if offset > span {
+        min + 2.0 * span - offset
+    } else {
+        min + offset
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Rectangle {
     pub bottom_left: Vector,
     pub top_right: Vector,
@@ -85,6 +131,13 @@ impl Rectangle {
             y: self.bottom_left.y + self.height() * 0.5,
         }
     }
+
+    pub fn contains(self, point: Vector) -> bool {
+        point.x >= self.bottom_left.x
+            && point.x <= self.top_right.x
+            && point.y >= self.bottom_left.y
+            && point.y <= self.top_right.y
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -93,20 +146,199 @@ pub struct Circle {
     pub radius: f64,
 }
 
+/// Describes how a circle overlaps a rectangle.
+#[derive(Debug, Copy, Clone)]
+pub struct Contact {
+    /// Unit vector pointing from the rectangle towards the circle.
+    pub normal: Vector,
+    /// How far the circle must move along `normal` to stop overlapping.
+    pub penetration: f64,
+    /// Point of the rectangle's boundary closest to the circle's center.
+    pub point: Vector,
+}
+
 pub fn check_collision(rectangle: Rectangle, circle: Circle) -> bool {
-    let clamp_center_x = num::clamp(
-        circle.center.x,
-        rectangle.bottom_left.x,
-        rectangle.top_right.x,
+    find_contact(rectangle, circle).is_some()
+}
+
+/// Returns the contact between `rectangle` and `circle` if they overlap.
+pub fn find_contact(rectangle: Rectangle, circle: Circle) -> Option<Contact> {
+    let contact = closest_contact(rectangle, circle);
+    if contact.penetration > 0.0 {
+        Some(contact)
+    } else {
+        None
+    }
+}
+
+/// Returns the contact between `rectangle` and `circle` when `circle` is moved along
+/// `displacement` until it first touches `rectangle`, with the fraction of `displacement`
+/// travelled at that time.
+pub fn find_swept_contact(
+    rectangle: Rectangle,
+    circle: Circle,
+    displacement: Vector,
+) -> Option<(f64, Contact)> {
+    let time = time_of_impact(rectangle, circle, displacement)?;
+    let moved = Circle {
+        center: circle.center + displacement * time,
+        radius: circle.radius,
+    };
+    let mut contact = closest_contact(rectangle, moved);
+    contact.penetration = contact.penetration.max(0.0);
+    Some((time, contact))
+}
+
+/// Computes the contact as if the shapes were overlapping. Penetration is negative when
+/// they are apart.
+fn closest_contact(rectangle: Rectangle, circle: Circle) -> Contact {
+    let min = rectangle.bottom_left;
+    let max = rectangle.top_right;
+    let center = circle.center;
+    let closest = Vector::new(
+        num::clamp(center.x, min.x, max.x),
+        num::clamp(center.y, min.y, max.y),
     );
-    let clamp_center_y = num::clamp(
-        circle.center.y,
-        rectangle.bottom_left.y,
-        rectangle.top_right.y,
+
+    let offset = center - closest;
+    let distance = offset.length();
+    if distance > 0.0 {
+        return Contact {
+            normal: offset * (1.0 / distance),
+            penetration: circle.radius - distance,
+            point: closest,
+        };
+    }
+
+    // The center is inside the rectangle, push it out through the nearest side
+    let sides = [
+        (
+            center.x - min.x,
+            Vector::new(-1.0, 0.0),
+            Vector::new(min.x, center.y),
+        ),
+        (
+            max.x - center.x,
+            Vector::new(1.0, 0.0),
+            Vector::new(max.x, center.y),
+        ),
+        (
+            center.y - min.y,
+            Vector::new(0.0, -1.0),
+            Vector::new(center.x, min.y),
+        ),
+        (
+            max.y - center.y,
+            Vector::new(0.0, 1.0),
+            Vector::new(center.x, max.y),
+        ),
+    ];
+    let (depth, normal, point) =
+        sides.iter().cloned().fold(
+            sides[0],
+            |nearest, side| if side.0 < nearest.0 { side } else { nearest },
+        );
+    Contact {
+        normal,
+        penetration: depth + circle.radius,
+        point,
+    }
+}
+
+/// Sweeps `circle` along `displacement` and returns the fraction of the displacement
+/// (between 0 and 1) at which it first touches `rectangle`, if it does.
+///
+/// The circle is treated as a ray cast against the rectangle inflated by the circle's
+/// radius (a rounded rectangle). A circle already overlapping the rectangle hits at 0, unless
+/// it is moving out of it.
+pub fn time_of_impact(rectangle: Rectangle, circle: Circle, displacement: Vector) -> Option<f64> {
+    if let Some(contact) = find_contact(rectangle, circle) {
+        return if displacement.dot(contact.normal) < 0.0 {
+            Some(0.0)
+        } else {
+            None
+        };
+    }
+
+    let radius = circle.radius;
+    let min = rectangle.bottom_left + Vector::new(-radius, -radius);
+    let max = rectangle.top_right + Vector::new(radius, radius);
+
+    let (enter_x, exit_x) = slab(circle.center.x, displacement.x, min.x, max.x)?;
+    let (enter_y, exit_y) = slab(circle.center.y, displacement.y, min.y, max.y)?;
+    let enter = enter_x.max(enter_y);
+    let exit = exit_x.min(exit_y);
+    if enter > exit || enter > 1.0 || exit < 0.0 {
+        return None;
+    }
+
+    let time = enter.max(0.0);
+    let hit = circle.center + displacement * time;
+    let in_x_band = hit.x >= rectangle.bottom_left.x && hit.x <= rectangle.top_right.x;
+    let in_y_band = hit.y >= rectangle.bottom_left.y && hit.y <= rectangle.top_right.y;
+    if in_x_band || in_y_band {
+        return Some(time);
+    }
+
+    // The ray entered the inflated box through one of its corner squares, so the
+    // actual surface there is a quarter circle around the rectangle's corner.
+    let corner = Vector::new(
+        num::clamp(hit.x, rectangle.bottom_left.x, rectangle.top_right.x),
+        num::clamp(hit.y, rectangle.bottom_left.y, rectangle.top_right.y),
     );
-    let dist_x = clamp_center_x - circle.center.x;
-    let dist_y = clamp_center_y - circle.center.y;
-    (dist_x * dist_x + dist_y * dist_y) < (circle.radius * circle.radius)
+    ray_circle(circle.center, displacement, corner, radius).filter(|t| *t <= 1.0)
+}
+
+/// Returns the entry and exit times of a 1D ray against the `[min, max]` slab.
+fn slab(origin: f64, direction: f64, min: f64, max: f64) -> Option<(f64, f64)> {
+    if direction == 0.0 {
+        if origin < min || origin > max {
+            return None;
+        }
+        return Some((f64::NEG_INFINITY, f64::INFINITY));
+    }
+    let t1 = (min - origin) / direction;
+    let t2 = (max - origin) / direction;
+    Some((t1.min(t2), t1.max(t2)))
+}
+
+/// Returns the first non negative time at which a ray hits a circle.
+fn ray_circle(origin: Vector, direction: Vector, center: Vector, radius: f64) -> Option<f64> {
+    let offset = origin - center;
+    let a = direction.dot(direction);
+    let b = offset.dot(direction);
+    let c = offset.dot(offset) - radius * radius;
+    let discriminant = b * b - a * c;
+    if a == 0.0 || discriminant < 0.0 {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()) / a;
+    if t < 0.0 {
+        return None;
+    }
+    Some(t)
+}
+
+#[cfg(test)]
+mod bounce_within_tests {
+    use super::*;
+
+    #[test]
+    fn it_should_keep_values_in_range() {
+        assert!((bounce_within(0.3, 0.0, 1.0) - 0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_should_bounce_once_off_each_bound() {
+        assert!((bounce_within(1.2, 0.0, 1.0) - 0.8).abs() < 1e-9);
+        assert!((bounce_within(-0.2, 0.0, 1.0) - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_should_bounce_several_times() {
+        assert!((bounce_within(2.3, 0.0, 1.0) - 0.3).abs() < 1e-9);
+        assert!((bounce_within(3.25, 0.5, 1.5) - 1.25).abs() < 1e-9);
+    }
 }
 
 #[cfg(test)]
@@ -153,3 +385,153 @@ mod collision_tests {
         assert!(!check_collision(rectangle, circle));
     }
 }
+
+#[cfg(test)]
+mod contact_tests {
+    use super::*;
+
+    fn rectangle() -> Rectangle {
+        Rectangle::new(Vector::new(-5.0, -5.0), Vector::new(5.0, 5.0))
+    }
+
+    #[test]
+    fn it_should_push_out_through_face() {
+        let circle = Circle {
+            center: Vector::new(0.0, 6.0),
+            radius: 2.0,
+        };
+        let contact = find_contact(rectangle(), circle).unwrap();
+        assert_eq!(Vector::new(0.0, 1.0), contact.normal);
+        assert!((contact.penetration - 1.0).abs() < 1e-9);
+        assert_eq!(Vector::new(0.0, 5.0), contact.point);
+    }
+
+    #[test]
+    fn it_should_push_out_diagonally_from_corner() {
+        let circle = Circle {
+            center: Vector::new(6.0, 6.0),
+            radius: 2.0,
+        };
+        let contact = find_contact(rectangle(), circle).unwrap();
+        let diagonal = Vector::new(1.0, 1.0).normalize();
+        assert!((contact.normal - diagonal).length() < 1e-9);
+        assert!((contact.penetration - (2.0 - 2.0_f64.sqrt())).abs() < 1e-9);
+        assert_eq!(Vector::new(5.0, 5.0), contact.point);
+    }
+
+    #[test]
+    fn it_should_push_out_through_nearest_side_when_center_is_inside() {
+        let circle = Circle {
+            center: Vector::new(4.0, 1.0),
+            radius: 0.5,
+        };
+        let contact = find_contact(rectangle(), circle).unwrap();
+        assert_eq!(Vector::new(1.0, 0.0), contact.normal);
+        assert!((contact.penetration - 1.5).abs() < 1e-9);
+        assert_eq!(Vector::new(5.0, 1.0), contact.point);
+    }
+
+    #[test]
+    fn it_should_not_find_contact_when_separate() {
+        let circle = Circle {
+            center: Vector::new(0.0, 8.0),
+            radius: 2.0,
+        };
+        assert!(find_contact(rectangle(), circle).is_none());
+    }
+
+    #[test]
+    fn it_should_find_touching_contact_on_side_when_swept() {
+        let circle = Circle {
+            center: Vector::new(8.0, 0.0),
+            radius: 1.0,
+        };
+        let (time, contact) =
+            find_swept_contact(rectangle(), circle, Vector::new(-4.0, 0.0)).unwrap();
+        assert!((time - 0.5).abs() < 1e-9);
+        assert_eq!(Vector::new(1.0, 0.0), contact.normal);
+        assert_eq!(0.0, contact.penetration);
+    }
+}
+
+#[cfg(test)]
+mod time_of_impact_tests {
+    use super::*;
+
+    fn paddle() -> Rectangle {
+        Rectangle::new(Vector::new(0.375, 0.0), Vector::new(0.625, 0.05))
+    }
+
+    #[test]
+    fn it_should_hit_when_moving_through_thin_rectangle() {
+        let circle = Circle {
+            center: Vector::new(0.5, 0.5),
+            radius: 0.025,
+        };
+        let time = time_of_impact(paddle(), circle, Vector::new(0.0, -1.0)).unwrap();
+        assert!((time - 0.425).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_should_not_hit_when_moving_away() {
+        let circle = Circle {
+            center: Vector::new(0.5, 0.5),
+            radius: 0.025,
+        };
+        assert!(time_of_impact(paddle(), circle, Vector::new(0.0, 1.0)).is_none());
+    }
+
+    #[test]
+    fn it_should_not_hit_when_stopping_short() {
+        let circle = Circle {
+            center: Vector::new(0.5, 0.5),
+            radius: 0.025,
+        };
+        assert!(time_of_impact(paddle(), circle, Vector::new(0.0, -0.4)).is_none());
+    }
+
+    #[test]
+    fn it_should_hit_rounded_corner() {
+        let circle = Circle {
+            center: Vector::new(0.7, 0.125),
+            radius: 0.025,
+        };
+        let time = time_of_impact(paddle(), circle, Vector::new(-0.1, -0.1)).unwrap();
+        let center = circle.center + Vector::new(-0.1, -0.1) * time;
+        let corner_distance = (center - Vector::new(0.625, 0.05)).length();
+        assert!((corner_distance - 0.025).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_should_miss_rounded_corner() {
+        let circle = Circle {
+            center: Vector::new(0.7, 0.021),
+            radius: 0.025,
+        };
+        assert!(time_of_impact(paddle(), circle, Vector::new(-0.1, 0.1)).is_none());
+    }
+
+    #[test]
+    fn it_should_hit_immediately_when_moving_into_an_overlap() {
+        let circle = Circle {
+            center: Vector::new(0.5, 0.06),
+            radius: 0.025,
+        };
+        assert_eq!(
+            Some(0.0),
+            time_of_impact(paddle(), circle, Vector::new(0.0, -1.0))
+        );
+    }
+
+    #[test]
+    fn it_should_not_hit_when_moving_out_of_an_overlap() {
+        let circle = Circle {
+            center: Vector::new(0.5, 0.06),
+            radius: 0.025,
+        };
+        assert_eq!(
+            None,
+            time_of_impact(paddle(), circle, Vector::new(0.0, 1.0))
+        );
+    }
+}